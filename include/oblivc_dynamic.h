@@ -0,0 +1,25 @@
+#ifndef OBLIVC_DYNAMIC_H
+#define OBLIVC_DYNAMIC_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define OBLIVC_LAYOUT_VERSION 1
+
+// Describes the argument type of a protocol, so that `oblivc::dynamic` can check it before the
+// protocol is loaded from a shared library.
+typedef struct {
+  uint32_t version;
+  size_t size;
+  size_t align;
+  const char* type_name;
+} oblivc_layout;
+
+// Exports the layout of `arg_type` as the argument of `protocol` under the symbol
+// `oblivc_layout_<protocol>`, e.g., `OBLIVC_PROTOCOL(millionaire, millionaire_args);`.
+#define OBLIVC_PROTOCOL(protocol, arg_type) \
+  const oblivc_layout oblivc_layout_##protocol = { \
+    OBLIVC_LAYOUT_VERSION, sizeof(arg_type), __alignof__(arg_type), #arg_type \
+  }
+
+#endif
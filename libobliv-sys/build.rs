@@ -2,11 +2,21 @@ extern crate bindgen;
 extern crate cc;
 extern crate walkdir;
 
+use std::collections::HashSet;
 use std::env;
+use std::fs;
 use std::process::Command;
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
+// Prefix for shell commands that need the OCaml tools installed via opam
+const OPAM_ENV: &str = "eval `opam config env` && ";
+// OCaml packages needed by Obliv-C's compiler
+const OCAML_PACKAGES: &[&str] = &["batteries", "camlp4", "ocamlbuild"];
+// Directories of the Obliv-C tree with the oblivious libraries (ORAM variants and the data
+// structures they are built from) that the `extensions` feature compiles into `libobliv_ext.a`
+const EXTENSION_DIRS: &[&str] = &["test/oblivc/oram"];
+
 macro_rules! t {
     ($e:expr) => (match $e{
         Ok(e) => e,
@@ -31,55 +41,65 @@ fn main() {
         t!(env::current_dir()).join(oblivc_path)
     };
 
-    // update submodule, but ignore if that fails
-    if !oblivc_path.join(".git").exists() {
-        let _status = Command::new("git")
-            .args(&["submodule", "update", "--init"])
-            .status();
-    }
+    // with OBLIVC_PREBUILT set, OBLIVC_PATH is an existing installation that is used as is
+    let prebuilt = env::var("OBLIVC_PREBUILT")
+        .ok()
+        .map_or(false, |s| s != "" && s != "0");
+    let out_path = PathBuf::from(t!(env::var("OUT_DIR")));
+    let installation = if prebuilt {
+        find_installation(&oblivc_path)
+    } else {
+        build(&oblivc_path, &out_path)
+    };
+    let out_bin_path = installation.root.join("bin");
 
-    // build obliv-c
-    // TODO(schoppmp): Find a nicer way to pass OPAM environment to configure and make.
-    if !oblivc_path.join("Makefile").exists() {
-        let status = t!(Command::new("sh")
-            .arg("-c")
-            .arg("eval `opam config env` && ./configure")
-            .current_dir(&oblivc_path)
-            .status());
-        if !status.success() {
-            panic!("Configuring Obliv-C failed");
-        }
-    }
-    let status = t!(Command::new("sh")
-        .arg("-c")
-        .arg("eval `opam config env` && make CFLAGS=\"-fPIC -std=gnu99 -O3\"")
-        .current_dir(&oblivc_path)
-        .status());
-    if !status.success() {
-        panic!("Building Obliv-C failed; PATH={}", env!("PATH"));
-    }
+    // record the Obliv-C revision, so that parties can detect version mismatches
+    let revision = git_revision(&oblivc_path).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=OBLIVC_REVISION={}", revision);
 
-    let oblivc_src_path = oblivc_path.join("src");
-    let oblivc_bin_path = oblivc_path.join("bin");
-    let oblivc_libobliv_path = oblivc_path.join("_build/libobliv.a");
-    let out_path = PathBuf::from(t!(env::var("OUT_DIR")));
-    let out_libobliv_path = out_path.join("libobliv.a");
-    let out_bin_path = out_path.join("bin");
-
-    // delete previous symlinks, ignoring errors
-    let _ = std::fs::remove_file(&out_libobliv_path);
-    let _ = std::fs::remove_file(&out_bin_path);
-    // link oblivcc and libobliv.a to OUT_DIR
-    t!(std::os::unix::fs::symlink(
-        &oblivc_libobliv_path,
-        &out_libobliv_path
-    ));
-    t!(std::os::unix::fs::symlink(&oblivc_bin_path, &out_bin_path));
+    // collect include paths (including dependencies)
+    let mut include_paths = vec![
+        installation.include_dir.clone(),
+        PathBuf::from(t!(env::var("DEP_GCRYPT_ROOT"))).join("include"),
+        PathBuf::from(t!(env::var("DEP_GPG_ERROR_ROOT"))).join("include"),
+    ];
 
-    // tell cargo to tell rustc to link libobliv.a
-    println!("cargo:root={}", oblivc_path.display());
-    println!("cargo:rustc-link-search=native={}", out_path.display());
-    println!("cargo:rustc-link-lib=static=obliv");
+    // tell cargo to tell rustc to link libobliv, either the archive or a shared library made from
+    // it, so that several protocol libraries loaded at runtime share one runtime
+    let shared = env::var_os("CARGO_FEATURE_SHARED").is_some();
+    println!("cargo:root={}", installation.root.display());
+    let lib_dir = if shared {
+        let mut args = Vec::new();
+        for &(var, lib) in &[("DEP_GCRYPT_ROOT", "gcrypt"), ("DEP_GPG_ERROR_ROOT", "gpg-error")] {
+            let root = PathBuf::from(t!(env::var(var)));
+            args.push(format!("-L{}", root.join("lib").display()));
+            args.push(format!("-l{}", lib));
+        }
+        for sanitizer in &sanitizers() {
+            args.push(format!("-fsanitize={}", sanitizer));
+        }
+        link_shared(&installation.lib_dir.join("libobliv.a"), &out_path, "obliv", &args);
+        out_path.clone()
+    } else {
+        installation.lib_dir.clone()
+    };
+    // the extensions use the runtime, so they have to precede it on the linker command line
+    let extensions = env::var_os("CARGO_FEATURE_EXTENSIONS").is_some();
+    if extensions {
+        let extension_dirs = build_extensions(&installation, &include_paths, shared);
+        include_paths.extend(extension_dirs);
+    }
+    println!(
+        "cargo:rustc-link-lib={}=obliv",
+        if shared { "dylib" } else { "static" }
+    );
+    println!("cargo:rustc-link-search=native={}", lib_dir.display());
+    // the directory containing the library to link, for protocol libraries built on top of it
+    println!("cargo:lib={}", lib_dir.display());
+    println!(
+        "cargo:include={}",
+        t!(env::join_paths(&include_paths)).to_str().unwrap()
+    );
 
     // register to rebuild when Obliv-C sources change
     let register_dir_rebuild = |dir: &AsRef<Path>| {
@@ -87,23 +107,30 @@ fn main() {
             println!("cargo:rerun-if-changed={}", file.path().display());
         }
     };
-    register_dir_rebuild(&oblivc_src_path);
-    register_dir_rebuild(&oblivc_bin_path);
+    if prebuilt {
+        println!("cargo:rerun-if-changed={}", out_bin_path.join("oblivcc").display());
+        println!(
+            "cargo:rerun-if-changed={}",
+            installation.lib_dir.join("libobliv.a").display()
+        );
+        // the include directory of an installation may contain unrelated headers
+        for header in oblivc_headers(&installation.include_dir) {
+            println!("cargo:rerun-if-changed={}", header.display());
+        }
+    } else {
+        register_dir_rebuild(&oblivc_path.join("src"));
+        register_dir_rebuild(&oblivc_path.join("bin"));
+    }
+    if extensions {
+        for dir in EXTENSION_DIRS {
+            register_dir_rebuild(&oblivc_path.join(dir));
+        }
+    }
     register_dir_rebuild(&"src");
-    println!("cargo:rerun-if-changed={}", oblivc_libobliv_path.display());
-    // also rerun if OBLIVC_PATH changes
+    // also rerun if any of the settings change
     println!("cargo:rerun-if-env-changed=OBLIVC_PATH");
-
-    // collect include paths (including dependencies)
-    let include_paths = vec![
-        oblivc_path.join("src/ext/oblivc"),
-        PathBuf::from(t!(env::var("DEP_GCRYPT_ROOT"))).join("include"),
-        PathBuf::from(t!(env::var("DEP_GPG_ERROR_ROOT"))).join("include"),
-    ];
-    println!(
-        "cargo:include={}",
-        t!(env::join_paths(&include_paths)).to_str().unwrap()
-    );
+    println!("cargo:rerun-if-env-changed=OBLIVC_PREBUILT");
+    println!("cargo:rerun-if-env-changed=OBLIVC_SANITIZE");
 
     // all functions in "obliv.h", but not in included headers
     let bind_functions = vec![
@@ -135,7 +162,7 @@ fn main() {
         .iter()
         .fold(
             bindgen::Builder::default()
-                .header(oblivc_path.join("src/ext/oblivc/obliv.h").to_str().unwrap()),
+                .header(installation.include_dir.join("obliv.h").to_str().unwrap()),
             |builder, func| builder.whitelist_function(func),
         )
         .clang_args(include_paths.iter().map(|p| format!("-I{}", p.display())))
@@ -162,3 +189,429 @@ fn main() {
         .file("src/test_oblivc.oc")
         .compile("libtest_oblivc.a")
 }
+
+// Locations of a usable Obliv-C installation
+struct Installation {
+    // contains `bin/oblivcc`
+    root: PathBuf,
+    // contains `libobliv.a`
+    lib_dir: PathBuf,
+    // contains `obliv.h`
+    include_dir: PathBuf,
+}
+
+// Builds Obliv-C from the sources at `oblivc_path` in a copy below `out_path`.
+fn build(oblivc_path: &Path, out_path: &Path) -> Installation {
+    if !oblivc_path.join("configure").is_file() {
+        panic!(
+            "No Obliv-C sources found at {}. Run `git submodule update --init` to fetch the \
+             bundled sources, or set OBLIVC_PATH to an Obliv-C source tree.",
+            oblivc_path.display()
+        );
+    }
+
+    check_prerequisites();
+
+    // build obliv-c in a copy of the sources, so that they are never modified
+    let build_path = out_path.join("obliv-c");
+    copy_tree(oblivc_path, &build_path);
+    // TODO(schoppmp): Find a nicer way to pass OPAM environment to configure and make.
+    if !build_path.join("Makefile").exists() {
+        let status = t!(Command::new("sh")
+            .arg("-c")
+            .arg(format!("{}./configure", OPAM_ENV))
+            .current_dir(&build_path)
+            .status());
+        if !status.success() {
+            panic!("Configuring Obliv-C failed");
+        }
+    }
+    // `make` does not track flags, so objects built with different ones are removed first
+    let sanitizers = sanitizers();
+    let cflags = runtime_cflags(&sanitizers);
+    let cflags_path = build_path.join(".cflags");
+    let previous_cflags = fs::read_to_string(&cflags_path).ok();
+    if previous_cflags.map_or(false, |previous| previous != cflags) {
+        let status = t!(Command::new("sh")
+            .arg("-c")
+            .arg(format!("{}make clean", OPAM_ENV))
+            .current_dir(&build_path)
+            .status());
+        if !status.success() {
+            panic!("Cleaning the Obliv-C build failed");
+        }
+    }
+    let status = t!(Command::new("sh")
+        .arg("-c")
+        .arg(format!("{}make CFLAGS=\"{}\"", OPAM_ENV, cflags))
+        .current_dir(&build_path)
+        .status());
+    if !status.success() {
+        panic!("Building Obliv-C failed; PATH={}", env!("PATH"));
+    }
+    t!(fs::write(&cflags_path, &cflags));
+    // the sanitizers' runtime libraries are needed by everything linking libobliv.a
+    for sanitizer in &sanitizers {
+        let runtime = match &sanitizer[..] {
+            "address" => "asan",
+            "undefined" => "ubsan",
+            "thread" => "tsan",
+            "leak" => "lsan",
+            _ => continue,
+        };
+        println!("cargo:rustc-link-lib=dylib={}", runtime);
+    }
+
+    // copy libobliv.a to OUT_DIR, where rustc looks for it
+    t!(fs::copy(
+        build_path.join("_build/libobliv.a"),
+        out_path.join("libobliv.a")
+    ));
+    Installation {
+        include_dir: build_path.join("src/ext/oblivc"),
+        root: build_path,
+        lib_dir: out_path.to_path_buf(),
+    }
+}
+
+// Compiles the libraries in `EXTENSION_DIRS` with `oblivcc` into `libobliv_ext.a`, or
+// `libobliv_ext.so` if `shared` is set, tells Cargo to link it, and returns the directories
+// containing their headers.
+fn build_extensions(
+    installation: &Installation,
+    include_paths: &[PathBuf],
+    shared: bool,
+) -> Vec<PathBuf> {
+    let dirs: Vec<PathBuf> = EXTENSION_DIRS
+        .iter()
+        .map(|dir| installation.root.join(dir))
+        .collect();
+    let missing: Vec<String> = dirs
+        .iter()
+        .filter(|dir| !dir.is_dir())
+        .map(|dir| dir.display().to_string())
+        .collect();
+    if !missing.is_empty() {
+        panic!(
+            "The `extensions` feature needs the sources of Obliv-C's libraries, \
+             but {} does not exist",
+            missing.join(", ")
+        );
+    }
+
+    let mut build = cc::Build::new();
+    build
+        .compiler(installation.root.join("bin/oblivcc"))
+        .cargo_metadata(!shared);
+    for dir in include_paths.iter().chain(&dirs) {
+        build.include(dir);
+    }
+    for sanitizer in &sanitizers() {
+        build.flag(&format!("-fsanitize={}", sanitizer));
+    }
+    for dir in &dirs {
+        let mut sources: Vec<PathBuf> = t!(fs::read_dir(dir))
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let is_source = path
+                    .extension()
+                    .map_or(false, |ext| ext == "c" || ext == "oc");
+                is_source && !defines_main(path)
+            })
+            .collect();
+        sources.sort();
+        build.files(sources);
+    }
+    build.compile("obliv_ext");
+    if shared {
+        let out_path = PathBuf::from(t!(env::var("OUT_DIR")));
+        let args = [format!("-L{}", out_path.display()), "-lobliv".to_string()];
+        link_shared(&out_path.join("libobliv_ext.a"), &out_path, "obliv_ext", &args);
+        println!("cargo:rustc-link-lib=dylib=obliv_ext");
+    }
+    dirs
+}
+
+// Returns whether the source file at `path` defines `main`, i.e., is a test or benchmark driver
+// rather than part of a library.
+fn defines_main(path: &Path) -> bool {
+    let source = t!(fs::read_to_string(path));
+    source.lines().any(|line| {
+        let line = line.trim_start();
+        let has_return_type = line.starts_with("int ") || line.starts_with("void ");
+        let name = line.split('(').next().and_then(|head| head.split_whitespace().last());
+        has_return_type && line.contains('(') && name == Some("main")
+    })
+}
+
+// Links all objects of `archive` into `lib<name>.so` in `out_path`, passing `args` to the linker.
+fn link_shared(archive: &Path, out_path: &Path, name: &str, args: &[String]) {
+    let library = out_path.join(format!("lib{}.so", name));
+    let status = t!(cc::Build::new()
+        .get_compiler()
+        .to_command()
+        .arg("-shared")
+        .arg("-o")
+        .arg(&library)
+        .arg("-Wl,--whole-archive")
+        .arg(archive)
+        .arg("-Wl,--no-whole-archive")
+        .args(args)
+        .status());
+    if !status.success() {
+        panic!("Linking {} failed", library.display());
+    }
+}
+
+// Returns the sanitizers listed in OBLIVC_SANITIZE, e.g., `address,undefined`.
+// Returns the commit checked out at `path`, if `path` is the root of a git repository. The HEAD
+// of an enclosing repository, e.g., of a project that vendors this crate, says nothing about
+// the Obliv-C sources.
+fn git_revision(path: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "--show-toplevel", "HEAD"])
+        .current_dir(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let mut lines = stdout.lines();
+    let toplevel = PathBuf::from(lines.next()?);
+    let head = lines.next()?.trim().to_string();
+    if toplevel.canonicalize().ok()? != path.canonicalize().ok()? {
+        return None;
+    }
+    Some(head)
+}
+
+fn sanitizers() -> Vec<String> {
+    env::var("OBLIVC_SANITIZE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+// Returns the C flags for building Obliv-C's runtime, following the optimization and debug
+// settings of the current Cargo profile.
+fn runtime_cflags(sanitizers: &[String]) -> String {
+    let mut flags = vec!["-fPIC".to_string(), "-std=gnu99".to_string()];
+    let opt_level = env::var("OPT_LEVEL").unwrap_or_else(|_| "3".to_string());
+    // gcc does not know `-Oz`
+    flags.push(format!("-O{}", if opt_level == "z" { "s" } else { &opt_level[..] }));
+    if env::var("DEBUG").ok().map_or(false, |debug| debug == "true") {
+        flags.push("-g".to_string());
+    }
+    if !sanitizers.is_empty() {
+        flags.push(format!("-fsanitize={}", sanitizers.join(",")));
+        flags.push("-fno-omit-frame-pointer".to_string());
+    }
+    flags.join(" ")
+}
+
+// A tool or library needed to build Obliv-C
+struct Prerequisite {
+    name: String,
+    // shell command that succeeds if the prerequisite is available
+    check: String,
+    // how to install the prerequisite
+    hint: &'static str,
+    // another prerequisite that `check` relies on; it is skipped if that one is missing
+    requires: Option<&'static str>,
+}
+
+// Checks that everything needed to build Obliv-C is available. Panics with a list of all
+// missing prerequisites, including how they were searched for.
+fn check_prerequisites() {
+    let gcrypt_include = PathBuf::from(t!(env::var("DEP_GCRYPT_ROOT"))).join("include");
+    let mut prerequisites = vec![
+        Prerequisite {
+            name: "opam".to_string(),
+            check: "opam --version".to_string(),
+            hint: "install opam, e.g., via your package manager, and run `opam init`",
+            requires: None,
+        },
+        Prerequisite {
+            name: "ocamlfind".to_string(),
+            check: format!("{}ocamlfind ocamlopt -version", OPAM_ENV),
+            hint: "run `opam install ocamlfind`",
+            requires: Some("opam"),
+        },
+        Prerequisite {
+            name: "gcc".to_string(),
+            check: "gcc --version".to_string(),
+            hint: "install gcc, e.g., via your package manager",
+            requires: None,
+        },
+        Prerequisite {
+            name: "libgcrypt headers".to_string(),
+            check: format!(
+                "echo '#include <gcrypt.h>' | gcc -E -x c - -I{} > /dev/null",
+                gcrypt_include.display()
+            ),
+            hint: "install the libgcrypt development package, e.g., `libgcrypt20-dev`",
+            requires: Some("gcc"),
+        },
+    ];
+    prerequisites.extend(OCAML_PACKAGES.iter().map(|package| Prerequisite {
+        name: format!("OCaml package `{}`", package),
+        check: format!("{}ocamlfind query {}", OPAM_ENV, package),
+        hint: "run `opam install` with the missing package",
+        requires: Some("ocamlfind"),
+    }));
+
+    let mut missing = Vec::new();
+    let mut messages = Vec::new();
+    for prerequisite in &prerequisites {
+        if let Some(required) = prerequisite.requires {
+            if missing.contains(&required) {
+                messages.push(format!(
+                    "* {}: not checked, since {} is missing",
+                    prerequisite.name, required
+                ));
+                missing.push(&prerequisite.name[..]);
+                continue;
+            }
+        }
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(&prerequisite.check)
+            .output();
+        let reason = match output {
+            Ok(ref output) if output.status.success() => continue,
+            Ok(output) => String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            Err(e) => e.to_string(),
+        };
+        missing.push(&prerequisite.name[..]);
+        messages.push(format!(
+            "* {}: `{}` failed{}\n  Hint: {}",
+            prerequisite.name,
+            prerequisite.check,
+            if reason.is_empty() {
+                String::new()
+            } else {
+                format!(" with:\n    {}", reason.replace("\n", "\n    "))
+            },
+            prerequisite.hint
+        ));
+    }
+    if !missing.is_empty() {
+        panic!(
+            "Prerequisites for building Obliv-C are missing (PATH={}):\n{}\n\
+             Alternatively, set OBLIVC_PATH and OBLIVC_PREBUILT to use a built Obliv-C.",
+            env::var("PATH").unwrap_or_default(),
+            messages.join("\n")
+        );
+    }
+}
+
+// Checks that `root` contains a built Obliv-C, either as a source tree built in place or
+// installed with `lib` and `include` directories, and returns its locations.
+fn find_installation(root: &Path) -> Installation {
+    let find = |name: &str, dirs: &[&str]| {
+        let candidates: Vec<_> = dirs.iter().map(|dir| root.join(dir)).collect();
+        match candidates.iter().find(|dir| dir.join(name).is_file()) {
+            Some(dir) => dir.clone(),
+            None => panic!(
+                "OBLIVC_PREBUILT is set, but `{}` was not found in any of {:?}. Set OBLIVC_PATH \
+                 to a built Obliv-C, or unset OBLIVC_PREBUILT to build it from source.",
+                name, candidates
+            ),
+        }
+    };
+    find("oblivcc", &["bin"]);
+    Installation {
+        root: root.to_path_buf(),
+        lib_dir: find("libobliv.a", &["_build", "lib"]),
+        include_dir: find("obliv.h", &["src/ext/oblivc", "include"]),
+    }
+}
+
+// Returns `obliv.h`, `obliv.oh` and the headers in `include_dir` they include, as reported by
+// the C preprocessor. Only the former two are returned if that fails.
+fn oblivc_headers(include_dir: &Path) -> Vec<PathBuf> {
+    let mut headers = vec![include_dir.join("obliv.h"), include_dir.join("obliv.oh")];
+    // `-MG` ignores missing headers of dependencies, which are not needed for listing ours
+    let output = cc::Build::new()
+        .get_compiler()
+        .to_command()
+        .arg(format!("-I{}", include_dir.display()))
+        .args(&["-MM", "-MG", "-x", "c"])
+        .args(&headers)
+        .output();
+    if let Ok(ref output) = output {
+        if output.status.success() {
+            let rules = String::from_utf8_lossy(&output.stdout);
+            // one rule of the form `target.o: dependencies...` per header
+            for dependency in rules.split_whitespace() {
+                let path = PathBuf::from(dependency);
+                if path.starts_with(include_dir) && !headers.contains(&path) {
+                    headers.push(path);
+                }
+            }
+        }
+    }
+    headers
+}
+
+// Mirrors the source tree at `src` into `dst`, skipping version control data and build results
+// of in-tree builds. Files are only copied if their contents differ, so that `make` does not
+// rebuild everything, and files copied by an earlier build that no longer exist in `src` are
+// removed. Other files in `dst`, i.e., build results, are kept.
+fn copy_tree(src: &Path, dst: &Path) {
+    // relative paths of the files copied by the last build
+    let manifest_path = dst.join(".copied");
+    let previous = fs::read_to_string(&manifest_path).unwrap_or_default();
+    let mut copied = HashSet::new();
+    let entries = WalkDir::new(src).into_iter().filter_entry(|e| {
+        let name = e.file_name().to_string_lossy();
+        name != ".git" && name != "_build"
+    });
+    for entry in entries {
+        let entry = t!(entry);
+        let relative = t!(entry.path().strip_prefix(src)).to_path_buf();
+        let target = dst.join(&relative);
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            t!(fs::create_dir_all(&target));
+            continue;
+        }
+        if file_type.is_symlink() {
+            let link = t!(fs::read_link(entry.path()));
+            if fs::read_link(&target).ok().as_ref() != Some(&link) {
+                let _ = fs::remove_file(&target);
+                t!(std::os::unix::fs::symlink(link, &target));
+            }
+        } else if differs(entry.path(), &target) {
+            // the target may be read-only, like its source, or a symlink copied earlier
+            let _ = fs::remove_file(&target);
+            t!(fs::copy(entry.path(), &target));
+        }
+        copied.insert(relative.to_string_lossy().into_owned());
+    }
+    for file in previous.lines() {
+        if !copied.contains(file) {
+            let _ = fs::remove_file(dst.join(file));
+        }
+    }
+    let manifest: Vec<&str> = copied.iter().map(|file| &file[..]).collect();
+    t!(fs::write(&manifest_path, manifest.join("\n")));
+}
+
+// Returns whether `dst` is missing or its contents differ from `src`.
+fn differs(src: &Path, dst: &Path) -> bool {
+    let len = |path: &Path| fs::metadata(path).map(|m| m.len());
+    match (len(src), len(dst)) {
+        (Ok(src_len), Ok(dst_len)) if src_len == dst_len => {
+            match (fs::read(src), fs::read(dst)) {
+                (Ok(src), Ok(dst)) => src != dst,
+                _ => true,
+            }
+        }
+        _ => true,
+    }
+}
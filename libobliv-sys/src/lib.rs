@@ -8,6 +8,18 @@ extern crate libgpg_error_sys;
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+/// The git revision of the Obliv-C sources this crate was built from, or `"unknown"` if it could
+/// not be determined.
+pub const OBLIVC_REVISION: &str = env!("OBLIVC_REVISION");
+
+// Declared in a header that is not passed to bindgen
+extern "C" {
+    /// Returns the number of gates garbled or evaluated so far by the Yao protocol that is
+    /// currently executed on this thread. Must only be called during `execYaoProtocol`, once the
+    /// protocol state in `ProtocolDesc::extra` is set up.
+    pub fn yaoGateCount() -> u64;
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,396 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use cc;
+
+use super::{bindings, compiler, OBLIVC_INCLUDE};
+use diagnostic::{Diagnostic, Level};
+
+/// Compiles Obliv-C protocols and generates Rust bindings for them, for use in build scripts.
+///
+/// A single call to [`compile`](#method.compile)
+/// * compiles all [source files](#method.file) into a static library and links it,
+/// * generates bindings for the functions and types declared via
+/// [`function`](#method.function) and [`type_`](#method.type_), as found in the
+/// [headers](#method.header), and writes them to one module file in `OUT_DIR`, and
+/// * tells Cargo to rerun the build script if any of the inputs change, including all headers
+/// they include.
+///
+/// With the `shared` feature, the sources are linked into the shared library `lib<name>.so`
+/// instead, which uses the shared Obliv-C runtime of `libobliv-sys`. Several such libraries can be
+/// loaded by one process without duplicating the runtime and its global state, see
+/// [`dynamic`](dynamic/index.html).
+///
+/// Errors and warnings of `oblivcc`, including those of its CIL front end, are reported as Cargo
+/// warnings of the form `file:line:column: level: message`, with paths relative to the crate.
+///
+/// Unlike the [`cc::Build`](../cc/struct.Build.html) returned by [`compiler`](fn.compiler.html),
+/// this silences the warnings caused by the headers of Obliv-C's dependencies, so that
+/// [`warnings_into_errors`](#method.warnings_into_errors) only reports warnings in the compiled
+/// sources. Optimization and debug information default to the settings of the current Cargo
+/// profile.
+///
+/// # Examples
+/// In `build.rs`:
+///
+/// ```no_run
+/// # extern crate oblivc;
+/// # fn main() {
+/// oblivc::Build::new()
+///     .file("src/millionaire.oc")
+///     .header("src/millionaire.h")
+///     .function("millionaire")
+///     .type_("millionaire_args")
+///     .include("src")
+///     .define("MAX_INPUT", Some("1000"))
+///     .warnings_into_errors(true)
+///     .compile("millionaire");
+/// # }
+/// ```
+///
+/// In the crate, the bindings are then included via
+/// `include!(concat!(env!("OUT_DIR"), "/millionaire.rs"));`.
+#[derive(Clone, Debug)]
+pub struct Build {
+    cc: cc::Build,
+    files: Vec<PathBuf>,
+    headers: Vec<PathBuf>,
+    includes: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    functions: Vec<String>,
+    types: Vec<String>,
+    cargo_metadata: bool,
+}
+impl Default for Build {
+    fn default() -> Self {
+        Build::new()
+    }
+}
+impl Build {
+    /// Returns a new [`Build`](#struct.Build) without any inputs.
+    pub fn new() -> Self {
+        let mut cc = compiler();
+        // warnings caused by libgcrypt's headers
+        cc.flag_if_supported("-Wno-unused-parameter")
+            .flag_if_supported("-Wno-unused-function")
+            .flag_if_supported("-Wno-unused-const-variable");
+        Build {
+            cc,
+            files: Vec::new(),
+            headers: Vec::new(),
+            includes: Vec::new(),
+            defines: Vec::new(),
+            functions: Vec::new(),
+            types: Vec::new(),
+            cargo_metadata: true,
+        }
+    }
+
+    /// Adds an Obliv-C or C source file to compile.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds a header to generate bindings from.
+    pub fn header<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.headers.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Generates bindings for the function `name`, which is declared in one of the
+    /// [headers](#method.header). Types used by the function are bound as well.
+    ///
+    /// If neither functions nor types are declared, bindings for everything visible in the
+    /// headers are generated, including items of the headers they include.
+    pub fn function(mut self, name: &str) -> Self {
+        self.functions.push(name.to_string());
+        self
+    }
+
+    /// Generates bindings for the type `name`, which is declared in one of the
+    /// [headers](#method.header).
+    pub fn type_(mut self, name: &str) -> Self {
+        self.types.push(name.to_string());
+        self
+    }
+
+    /// Adds a directory to the include path.
+    pub fn include<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.includes.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Defines the preprocessor macro `name`, with `value` if given.
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Sets the optimization level, i.e., `-O<level>`. Defaults to the `opt-level` of the current
+    /// Cargo profile.
+    pub fn opt_level(mut self, level: u32) -> Self {
+        self.cc.opt_level(level);
+        self
+    }
+
+    /// Sets whether debug information is generated. Defaults to the `debug` setting of the
+    /// current Cargo profile.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.cc.debug(debug);
+        self
+    }
+
+    /// Sets whether compiler warnings fail the build. Defaults to `false`.
+    pub fn warnings_into_errors(mut self, warnings_into_errors: bool) -> Self {
+        self.cc.warnings_into_errors(warnings_into_errors);
+        self
+    }
+
+    /// Passes an arbitrary `flag` to `oblivcc`.
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.cc.flag(flag);
+        self
+    }
+
+    /// Sets whether Cargo is told to link the compiled library. Defaults to `true`. Libraries
+    /// that are only [loaded at runtime](dynamic/index.html) should not be linked.
+    pub fn cargo_metadata(mut self, cargo_metadata: bool) -> Self {
+        self.cargo_metadata = cargo_metadata;
+        self
+    }
+
+    /// Compiles all source files into the static library `lib<name>.a`, or `$OUT_DIR/lib<name>.so`
+    /// with the `shared` feature, and instructs Cargo to link it. If headers were added, bindings
+    /// are written to `$OUT_DIR/<name>.rs`.
+    /// # Panics
+    /// * if not called from a build script, i.e., `OUT_DIR` is not set
+    /// * if compilation or generating bindings fails. The diagnostics of the compiler are
+    /// printed as Cargo warnings beforehand.
+    pub fn compile(self, name: &str) {
+        let out_dir = PathBuf::from(
+            env::var_os("OUT_DIR").expect("OUT_DIR is not set; `Build` is meant for build scripts"),
+        );
+        for path in self.files.iter().chain(&self.headers) {
+            for dependency in self.dependencies(path) {
+                println!("cargo:rerun-if-changed={}", dependency.display());
+            }
+        }
+
+        if !self.files.is_empty() {
+            let mut cc = self.cc.clone();
+            self.configure(&mut cc);
+            let objects = self.compile_objects(&cc, &out_dir.join(name), name);
+            #[cfg(feature = "shared")]
+            link_shared(&objects, &out_dir, name, self.cargo_metadata);
+            #[cfg(not(feature = "shared"))]
+            {
+                for object in objects {
+                    cc.object(object);
+                }
+                cc.cargo_metadata(self.cargo_metadata).compile(name);
+            }
+        }
+
+        if !self.headers.is_empty() {
+            let mut builder = bindings()
+                .clang_args(self.includes.iter().map(|p| format!("-I{}", p.display())))
+                .clang_args(define_args(&self.defines));
+            for header in &self.headers {
+                builder = builder.header(header.to_string_lossy().into_owned());
+            }
+            for function in &self.functions {
+                builder = builder.whitelist_function(function);
+            }
+            for ty in &self.types {
+                builder = builder.whitelist_type(ty);
+            }
+            builder
+                .generate()
+                .expect("Generating bindings failed")
+                .write_to_file(out_dir.join(format!("{}.rs", name)))
+                .expect("Writing bindings failed");
+        }
+    }
+
+    // Adds the include paths and defines to `cc`.
+    fn configure(&self, cc: &mut cc::Build) {
+        for dir in &self.includes {
+            cc.include(dir);
+        }
+        for &(ref name, ref value) in &self.defines {
+            cc.define(name, value.as_ref().map(|v| &v[..]));
+        }
+    }
+
+    // Compiles each source file into an object file in `dir` and returns their paths. Unlike
+    // `cc::Build::compile`, this captures the output of `oblivcc` and reports it as diagnostics.
+    fn compile_objects(&self, cc: &cc::Build, dir: &Path, name: &str) -> Vec<PathBuf> {
+        let base = env::var_os("CARGO_MANIFEST_DIR")
+            .map(PathBuf::from)
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_default();
+        let compiler = cc.get_compiler();
+        let mut objects = Vec::new();
+        let mut failed = Vec::new();
+        let mut errors = 0;
+        for file in &self.files {
+            let relative = file.strip_prefix(&base).unwrap_or(file);
+            let object = if relative.is_relative() {
+                dir.join(relative).with_extension("o")
+            } else {
+                dir.join(relative.file_name().expect("Source file has no name"))
+                    .with_extension("o")
+            };
+            if let Some(parent) = object.parent() {
+                fs::create_dir_all(parent).expect("Creating object directory failed");
+            }
+            let mut command = compiler.to_command();
+            command.arg("-c").arg("-o").arg(&object).arg(file);
+            let output = command.output().unwrap_or_else(|e| {
+                panic!("Failed to run {}: {}", compiler.path().display(), e)
+            });
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let lines: Vec<&str> = stdout.lines().chain(stderr.lines()).collect();
+            let diagnostics: Vec<Diagnostic> = lines
+                .iter()
+                .filter_map(|line| Diagnostic::parse(line))
+                .map(|d| d.relative_to(&base))
+                .collect();
+            for diagnostic in &diagnostics {
+                println!("cargo:warning={}", diagnostic);
+            }
+            let file_errors = diagnostics
+                .iter()
+                .filter(|d| d.level == Level::Error)
+                .count();
+            if !output.status.success() {
+                println!("cargo:warning=running: {:?}", command);
+                if file_errors == 0 {
+                    // Nothing could be parsed, so pass the output on unchanged.
+                    for line in &lines {
+                        println!("cargo:warning={}", line);
+                    }
+                }
+                errors += file_errors;
+                failed.push(relative.display().to_string());
+            }
+            objects.push(object);
+        }
+        if !failed.is_empty() {
+            match errors {
+                0 => panic!("Compiling `{}` failed in {}", name, failed.join(", ")),
+                1 => panic!("Compiling `{}` failed: 1 error in {}", name, failed.join(", ")),
+                n => panic!("Compiling `{}` failed: {} errors in {}", name, n, failed.join(", ")),
+            }
+        }
+        objects
+    }
+
+    // Returns `path` and all headers it includes, as reported by the C preprocessor. Only `path`
+    // is returned if that fails.
+    fn dependencies(&self, path: &Path) -> Vec<PathBuf> {
+        // `oblivcc` is not needed for preprocessing
+        let mut cc = cc::Build::new();
+        for dir in OBLIVC_INCLUDE.iter() {
+            cc.include(dir);
+        }
+        self.configure(&mut cc);
+        let command: Command = cc.get_compiler().to_command();
+        dependencies(command, path, b"").unwrap_or_else(|| {
+            println!(
+                "cargo:warning=Could not determine the headers included by {}",
+                path.display()
+            );
+            vec![path.to_path_buf()]
+        })
+    }
+}
+
+// Returns the `-D` arguments for the macros in `defines`.
+pub(crate) fn define_args(defines: &[(String, Option<String>)]) -> Vec<String> {
+    defines
+        .iter()
+        .map(|&(ref name, ref value)| match value {
+            &Some(ref value) => format!("-D{}={}", name, value),
+            &None => format!("-D{}", name),
+        })
+        .collect()
+}
+
+// Runs the C preprocessor `command` with `-MM` on `input`, passing `stdin` on its standard input,
+// which is read if `input` is `-`. Returns the files listed in the resulting rule, i.e., `input`
+// and all headers it includes, or `None` if preprocessing fails.
+pub(crate) fn dependencies(mut command: Command, input: &Path, stdin: &[u8]) -> Option<Vec<PathBuf>> {
+    let child = command
+        .arg("-MM")
+        .arg("-x")
+        .arg("c")
+        .arg(input)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let output = child.and_then(|mut child| {
+        let written = child
+            .stdin
+            .take()
+            .map_or(Ok(()), |mut pipe| pipe.write_all(stdin));
+        let output = child.wait_with_output();
+        written.and(output)
+    });
+    match output {
+        Ok(ref output) if output.status.success() => {
+            let rule = String::from_utf8_lossy(&output.stdout);
+            // the rule has the form `target: input dependencies...`, where `input` is omitted
+            // for standard input
+            let dependencies = rule.find(':').map_or("", |i| &rule[i + 1..]);
+            Some(
+                dependencies
+                    .split_whitespace()
+                    .filter(|s| *s != "\\")
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+// Adds the arguments for linking `objects` into the shared `library` to `command`, which runs
+// the plain C compiler: `oblivcc` would add the static runtime instead of the shared one.
+#[cfg(feature = "shared")]
+pub(crate) fn link_shared_args(command: &mut Command, library: &Path, objects: &[PathBuf]) {
+    command
+        .arg("-shared")
+        .arg("-o")
+        .arg(library)
+        .args(objects)
+        .arg(concat!("-L", env!("DEP_OBLIV_LIB")))
+        .arg("-lobliv");
+}
+
+// Links `objects` into `lib<name>.so` in `out_dir` and, if `cargo_metadata` is set, tells Cargo
+// to link it.
+#[cfg(feature = "shared")]
+fn link_shared(objects: &[PathBuf], out_dir: &Path, name: &str, cargo_metadata: bool) {
+    let library = out_dir.join(format!("lib{}.so", name));
+    let mut command = cc::Build::new().get_compiler().to_command();
+    link_shared_args(&mut command, &library, objects);
+    let status = command
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run the linker: {}", e));
+    if !status.success() {
+        panic!("Linking {} failed", library.display());
+    }
+    if cargo_metadata {
+        println!("cargo:rustc-link-search=native={}", out_dir.display());
+        println!("cargo:rustc-link-lib=dylib={}", name);
+    }
+}
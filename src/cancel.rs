@@ -0,0 +1,69 @@
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use libc;
+
+/// Cancels protocol executions of a [`ProtocolDesc`](struct.ProtocolDesc.html) from another
+/// thread. Obtained via [`ProtocolDesc::cancel_handle`][cancel_handle].
+///
+/// Once cancelled, all further communication of the descriptor fails, so that a running
+/// execution finishes promptly and returns an
+/// [`ExecError::Cancelled`](enum.ExecError.html). Later executions fail immediately.
+///
+/// [cancel_handle]: struct.ProtocolDesc.html#method.cancel_handle
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    // the native socket of the descriptor, if any. It is unregistered before the descriptor
+    // closes it, so that a reused file descriptor is never shut down.
+    socket: Mutex<Option<RawFd>>,
+}
+
+impl CancelHandle {
+    /// Cancels the current and all future executions. Native TCP connections are shut down,
+    /// which also interrupts calls blocked in Obliv-C. The socket of a connection created by
+    /// Obliv-C is only shut down if it could be identified after connecting; otherwise, an
+    /// execution that is already running is not interrupted.
+    ///
+    /// Streams passed to [`use_stream`](struct.ProtocolDesc.html#method.use_stream) are not
+    /// used after the current call to them returns, but a call that is already blocked is not
+    /// interrupted.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+        if let Some(socket) = *self.inner.socket.lock().unwrap() {
+            shutdown(socket);
+        }
+    }
+
+    /// Returns whether [`cancel`](#method.cancel) was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn cancelled_flag(&self) -> &AtomicBool {
+        &self.inner.cancelled
+    }
+
+    // Sets the socket shut down by `cancel`. If cancelled already, it is shut down immediately.
+    pub(crate) fn set_socket(&self, socket: Option<RawFd>) {
+        let mut guard = self.inner.socket.lock().unwrap();
+        *guard = socket;
+        if self.is_cancelled() {
+            if let Some(socket) = *guard {
+                shutdown(socket);
+            }
+        }
+    }
+}
+
+fn shutdown(socket: RawFd) {
+    unsafe {
+        libc::shutdown(socket, libc::SHUT_RDWR);
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::{self, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+/// A child process whose standard input and output are used as a bidirectional stream.
+///
+/// Passing a `ChildStream` to [`ProtocolDesc::use_stream`][use_stream] runs a protocol with a
+/// peer reached through the child, for example an `ssh` command that starts the other party on a
+/// remote host, or a sandboxed peer binary that calls [`use_stdio`][use_stdio].
+/// This allows tunneling protocols through existing secure channels without opening ports.
+///
+/// # Examples
+/// ```no_run
+/// # extern crate oblivc;
+/// # #[repr(C)] struct Args { input: i32, output: i8 }
+/// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+/// # fn main() {
+/// use std::process::Command;
+/// use oblivc::ChildStream;
+///
+/// let mut command = Command::new("ssh");
+/// command.args(&["peer.example.com", "millionaire-peer"]);
+/// let mut child = ChildStream::spawn(command).unwrap();
+///
+/// let mut args = Args { input: 10, output: 0 };
+/// let pd = oblivc::protocol_desc().party(oblivc::Party::garbler()).use_stream(&mut child);
+/// unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap() };
+/// assert!(child.wait().unwrap().success());
+/// # }
+/// ```
+///
+/// [use_stream]: struct.ProtocolDesc.html#method.use_stream
+/// [use_stdio]: struct.ProtocolDesc.html#method.use_stdio
+pub struct ChildStream {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: ChildStdout,
+}
+
+impl ChildStream {
+    /// Spawns `command` with piped standard input and output. Standard error is inherited unless
+    /// configured otherwise on `command`.
+    pub fn spawn(mut command: Command) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        // both are set, since they were configured as pipes
+        let stdin = child.stdin.take();
+        let stdout = child.stdout.take().unwrap();
+        Ok(ChildStream {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Returns the underlying child process.
+    pub fn child(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    /// Closes the child's standard input and waits for it to exit.
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        drop(self.stdin.take());
+        self.child.wait()
+    }
+}
+
+impl Read for ChildStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Write for ChildStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.stdin {
+            Some(ref mut stdin) => stdin.write(buf),
+            None => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Standard input of child process is closed",
+            )),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.stdin {
+            Some(ref mut stdin) => stdin.flush(),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The severity of a `Diagnostic`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+    Note,
+}
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+        })
+    }
+}
+
+/// A message of `oblivcc` that refers to a location in a source file.
+///
+/// Messages come from two stages: the CIL front end, which translates Obliv-C to C, and the C
+/// compiler that `oblivcc` invokes afterwards. CIL reports syntax errors as
+/// `file[line:column-column] : message` and other problems as `file:line: Error: message`, while
+/// the C compiler uses `file:line:column: error: message`. All of these are parsed into the same
+/// representation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub path: PathBuf,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub message: String,
+}
+impl Diagnostic {
+    /// Parses a single line of compiler output. Returns `None` for lines without a location,
+    /// e.g., include stacks, source excerpts or CIL's summary messages.
+    pub fn parse(line: &str) -> Option<Diagnostic> {
+        Diagnostic::parse_cil_syntax_error(line).or_else(|| Diagnostic::parse_located(line))
+    }
+
+    // `file[line:column-column] : message`
+    fn parse_cil_syntax_error(line: &str) -> Option<Diagnostic> {
+        let (path, rest) = split(line, "[")?;
+        let (location, message) = split(rest, "] : ")?;
+        let (line, columns) = split(location, ":")?;
+        let column = columns.split('-').next()?;
+        if path.is_empty() {
+            return None;
+        }
+        Some(Diagnostic {
+            level: Level::Error,
+            path: PathBuf::from(path),
+            line: line.parse().ok()?,
+            column: Some(column.parse().ok()?),
+            message: message.trim().to_string(),
+        })
+    }
+
+    // `file:line[:column]: level: message`
+    fn parse_located(line: &str) -> Option<Diagnostic> {
+        let (path, rest) = split(line, ":")?;
+        let (line, mut rest) = split(rest, ":")?;
+        if path.is_empty() {
+            return None;
+        }
+        let line = line.parse().ok()?;
+        let mut column = None;
+        if let Some((c, r)) = split(rest, ":") {
+            if let Ok(c) = c.parse() {
+                column = Some(c);
+                rest = r;
+            }
+        }
+        let (level, message) = split(rest.trim_start(), ":")?;
+        let level = match level {
+            "error" | "fatal error" | "Error" | "Bug" => Level::Error,
+            "warning" | "Warning" => Level::Warning,
+            "note" => Level::Note,
+            _ => return None,
+        };
+        Some(Diagnostic {
+            level,
+            path: PathBuf::from(path),
+            line,
+            column,
+            message: message.trim().to_string(),
+        })
+    }
+
+    /// Makes the path of the diagnostic relative to `base`, if it is inside `base`.
+    pub fn relative_to(mut self, base: &Path) -> Diagnostic {
+        if let Ok(path) = self.path.strip_prefix(base) {
+            self.path = path.to_path_buf();
+        }
+        self
+    }
+}
+// Splits `s` at the first occurrence of `separator`.
+fn split<'a>(s: &'a str, separator: &str) -> Option<(&'a str, &'a str)> {
+    s.find(separator).map(|i| (&s[..i], &s[i + separator.len()..]))
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)?;
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        write!(f, ": {}: {}", self.level, self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(level: Level, line: u32, column: Option<u32>, message: &str) -> Diagnostic {
+        Diagnostic {
+            level,
+            path: PathBuf::from("src/millionaire.oc"),
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_c_compiler() {
+        assert_eq!(
+            Diagnostic::parse("src/millionaire.oc:12:5: error: 'x' undeclared"),
+            Some(diagnostic(Level::Error, 12, Some(5), "'x' undeclared"))
+        );
+        assert_eq!(
+            Diagnostic::parse("src/millionaire.oc:3:10: fatal error: foo.h: No such file"),
+            Some(diagnostic(Level::Error, 3, Some(10), "foo.h: No such file"))
+        );
+        assert_eq!(
+            Diagnostic::parse("src/millionaire.oc:7: warning: unused variable"),
+            Some(diagnostic(Level::Warning, 7, None, "unused variable"))
+        );
+    }
+
+    #[test]
+    fn test_parse_cil() {
+        assert_eq!(
+            Diagnostic::parse("src/millionaire.oc[8:4-9] : syntax error"),
+            Some(diagnostic(Level::Error, 8, Some(4), "syntax error"))
+        );
+        assert_eq!(
+            Diagnostic::parse("src/millionaire.oc:15: Error: Cannot resolve variable y."),
+            Some(diagnostic(Level::Error, 15, None, "Cannot resolve variable y."))
+        );
+    }
+
+    #[test]
+    fn test_parse_ignored() {
+        for line in &[
+            "In file included from src/millionaire.oc:1:0:",
+            "src/millionaire.oc: In function 'millionaire':",
+            "Error: There were parsing errors in /tmp/cil-1234.i",
+            "   obliv int x = y;",
+            "",
+        ] {
+            assert_eq!(Diagnostic::parse(line), None, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_relative_to() {
+        let d = Diagnostic::parse("/home/user/crate/src/millionaire.oc:12:5: error: oops")
+            .unwrap()
+            .relative_to(Path::new("/home/user/crate"));
+        assert_eq!(d.to_string(), "src/millionaire.oc:12:5: error: oops");
+        let d = d.relative_to(Path::new("/elsewhere"));
+        assert_eq!(d.path, PathBuf::from("src/millionaire.oc"));
+    }
+}
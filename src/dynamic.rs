@@ -0,0 +1,736 @@
+//! Loading protocols from shared libraries at runtime.
+//!
+//! A [`Library`](struct.Library.html) is a shared library compiled by `oblivcc`, e.g., by
+//! [`Build`](../struct.Build.html) with the `shared` feature. Its protocols are looked up by their
+//! symbol name and returned as typed [`Protocol`](struct.Protocol.html) handles. To make sure that
+//! the Rust argument type matches the one a protocol was compiled for, the library has to export a
+//! descriptor of the argument's layout next to each protocol. The descriptor is created by the
+//! `OBLIVC_PROTOCOL` macro of `oblivc_dynamic.h`, which is on the include path of
+//! [`compiler`](../fn.compiler.html):
+//!
+//! ```c
+//! #include <oblivc_dynamic.h>
+//! #include "millionaire.h"
+//!
+//! OBLIVC_PROTOCOL(millionaire, millionaire_args);
+//! ```
+//!
+//! Loaded libraries and the program loading them should use the same Obliv-C runtime, i.e., both
+//! should be built with the `shared` feature, so that they share its global state. With that
+//! feature, a [`Compiler`](struct.Compiler.html) also compiles Obliv-C sources into libraries at
+//! runtime.
+//!
+//! # Examples
+//! ```no_run
+//! # extern crate oblivc;
+//! # fn main() {
+//! use oblivc::dynamic::Library;
+//!
+//! #[repr(C)]
+//! struct Args {
+//!     input: i32,
+//!     output: i8,
+//! }
+//!
+//! let library = unsafe { Library::open("libmillionaire.so") }.unwrap();
+//! let millionaire = library.protocol::<Args>("millionaire").unwrap();
+//! let mut args = Args { input: 10, output: 0 };
+//! let pd = oblivc::protocol_desc()
+//!     .party(oblivc::Party::garbler())
+//!     .accept("56748")
+//!     .unwrap();
+//! unsafe { millionaire.exec_yao_protocol(pd, &mut args).unwrap() };
+//! # }
+//! ```
+
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+#[cfg(feature = "shared")]
+use std::ffi::OsString;
+#[cfg(feature = "shared")]
+use std::process::{self, Command, Output};
+#[cfg(feature = "shared")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "shared")]
+use std::{env, fs, io};
+
+use libc;
+
+use super::{ExecError, ProtocolDesc, ProtocolFn};
+#[cfg(feature = "shared")]
+use super::{OBLIVC_INCLUDE, OBLIVC_ROOT};
+#[cfg(feature = "shared")]
+use build;
+#[cfg(feature = "shared")]
+use diagnostic::{Diagnostic, Level};
+#[cfg(feature = "shared")]
+use sha256::{self, Sha256};
+
+// The version of `oblivc_layout` in `oblivc_dynamic.h`
+const LAYOUT_VERSION: u32 = 1;
+// Prefix of the symbols created by `OBLIVC_PROTOCOL`
+const LAYOUT_PREFIX: &str = "oblivc_layout_";
+
+/// Error returned when loading a [`Library`](struct.Library.html) or one of its
+/// [`Protocol`](struct.Protocol.html)s fails.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The library could not be loaded
+    Open {
+        /// Path of the library
+        path: PathBuf,
+        /// Message of the dynamic linker
+        message: String,
+    },
+    /// The library does not contain a protocol of this name
+    Symbol {
+        /// Name of the protocol
+        name: String,
+        /// Message of the dynamic linker
+        message: String,
+    },
+    /// The protocol was not exported with `OBLIVC_PROTOCOL`, so its argument type is unknown
+    MissingLayout(String),
+    /// The layout descriptor of the protocol has a version not supported by this crate
+    UnsupportedLayout {
+        /// Name of the protocol
+        name: String,
+        /// Version of the descriptor
+        version: u32,
+    },
+    /// The argument type of the protocol differs from the requested one
+    LayoutMismatch {
+        /// Name of the protocol
+        name: String,
+        /// Name of the protocol's argument type
+        type_name: String,
+        /// Size and alignment of the protocol's argument type
+        found: (usize, usize),
+        /// Size and alignment of the requested argument type
+        expected: (usize, usize),
+    },
+}
+impl LoadError {
+    // The summary that starts the message of each variant.
+    fn message(&self) -> &str {
+        match self {
+            &LoadError::Open { .. } => "Could not load library",
+            &LoadError::Symbol { .. } => "Protocol not found",
+            &LoadError::MissingLayout(_) => "Protocol has no layout descriptor",
+            &LoadError::UnsupportedLayout { .. } => "Unsupported layout descriptor",
+            &LoadError::LayoutMismatch { .. } => "Argument types differ",
+        }
+    }
+}
+impl Error for LoadError {}
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &LoadError::Open {
+                ref path,
+                ref message,
+            } => write!(f, "{} {}: {}", self.message(), path.display(), message),
+            &LoadError::Symbol {
+                ref name,
+                ref message,
+            } => write!(f, "{}: `{}`: {}", self.message(), name, message),
+            &LoadError::MissingLayout(ref name) => write!(
+                f,
+                "{}: `{}` has to be exported with OBLIVC_PROTOCOL",
+                self.message(),
+                name
+            ),
+            &LoadError::UnsupportedLayout { ref name, version } => write!(
+                f,
+                "{}: `{}` uses version {}, but only version {} is supported",
+                self.message(),
+                name,
+                version,
+                LAYOUT_VERSION
+            ),
+            &LoadError::LayoutMismatch {
+                ref name,
+                ref type_name,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{}: `{}` takes `{}` of size {} and alignment {}, \
+                 but size {} and alignment {} were requested",
+                self.message(),
+                name,
+                type_name,
+                found.0,
+                found.1,
+                expected.0,
+                expected.1
+            ),
+        }
+    }
+}
+
+// `oblivc_layout` in `oblivc_dynamic.h`
+#[repr(C)]
+struct Layout {
+    version: u32,
+    size: usize,
+    align: usize,
+    type_name: *const c_char,
+}
+
+// Checks that `layout`, exported for the protocol `name`, describes `Arg`.
+fn check_layout<Arg>(name: &str, layout: &Layout) -> Result<(), LoadError> {
+    if layout.version != LAYOUT_VERSION {
+        return Err(LoadError::UnsupportedLayout {
+            name: name.to_string(),
+            version: layout.version,
+        });
+    }
+    let found = (layout.size, layout.align);
+    let expected = (mem::size_of::<Arg>(), mem::align_of::<Arg>());
+    if found != expected {
+        let type_name = if layout.type_name.is_null() {
+            String::from("?")
+        } else {
+            unsafe { CStr::from_ptr(layout.type_name) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        return Err(LoadError::LayoutMismatch {
+            name: name.to_string(),
+            type_name,
+            found,
+            expected,
+        });
+    }
+    Ok(())
+}
+
+// Returns the message of the last error of the dynamic linker.
+fn last_error() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        String::from("unknown error")
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+// A handle returned by `dlopen`, closed when dropped
+#[derive(Debug)]
+struct Handle(*mut c_void);
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { libc::dlclose(self.0) };
+    }
+}
+// The dynamic linker's functions are thread-safe.
+unsafe impl Send for Handle {}
+unsafe impl Sync for Handle {}
+
+/// A shared library containing Obliv-C protocols.
+///
+/// The library stays loaded as long as this value, one of its clones, or one of the
+/// [`Protocol`](struct.Protocol.html)s obtained from it exists.
+#[derive(Clone, Debug)]
+pub struct Library {
+    handle: Arc<Handle>,
+    path: PathBuf,
+}
+impl Library {
+    /// Loads the shared library at `path`. All of its symbols are resolved immediately.
+    ///
+    /// # Errors
+    /// If the library does not exist or cannot be loaded, a
+    /// [`LoadError::Open`](enum.LoadError.html) with the message of the dynamic linker is
+    /// returned.
+    ///
+    /// # Safety
+    /// Loading a library runs its initialization routines. `path` must refer to a trusted library
+    /// compiled by `oblivcc`.
+    pub unsafe fn open<P: AsRef<Path>>(path: P) -> Result<Library, LoadError> {
+        let path = path.as_ref().to_path_buf();
+        let open_error = |message: String| LoadError::Open {
+            path: path.clone(),
+            message,
+        };
+        let c_path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| open_error(String::from("path contains a null byte")))?;
+        let handle = libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return Err(open_error(last_error()));
+        }
+        Ok(Library {
+            handle: Arc::new(Handle(handle)),
+            path,
+        })
+    }
+
+    /// Returns the path this library was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Looks up the protocol `name`, whose argument has to be of type `Arg`.
+    ///
+    /// # Errors
+    /// * If the library contains no symbol `name`, a [`LoadError::Symbol`](enum.LoadError.html)
+    /// is returned.
+    /// * If the protocol was not exported with `OBLIVC_PROTOCOL`, a
+    /// [`LoadError::MissingLayout`](enum.LoadError.html) is returned.
+    /// * If the size or alignment of `Arg` differ from the protocol's argument type, a
+    /// [`LoadError::LayoutMismatch`](enum.LoadError.html) is returned.
+    pub fn protocol<Arg>(&self, name: &str) -> Result<Protocol<Arg>, LoadError> {
+        let function = self.symbol(name).map_err(|message| LoadError::Symbol {
+            name: name.to_string(),
+            message,
+        })?;
+        let layout = self.symbol(&format!("{}{}", LAYOUT_PREFIX, name))
+            .map_err(|_| LoadError::MissingLayout(name.to_string()))?;
+        check_layout::<Arg>(name, unsafe { &*(layout as *const Layout) })?;
+        Ok(Protocol {
+            name: name.to_string(),
+            function: unsafe { mem::transmute::<*mut c_void, ProtocolFn>(function) },
+            library: self.clone(),
+            arg: PhantomData,
+        })
+    }
+
+    // Returns the address of the symbol `name`, or the message of the dynamic linker.
+    fn symbol(&self, name: &str) -> Result<*mut c_void, String> {
+        let c_name =
+            CString::new(name).map_err(|_| String::from("name contains a null byte"))?;
+        unsafe {
+            // clear errors of earlier calls
+            libc::dlerror();
+            let symbol = libc::dlsym(self.handle.0, c_name.as_ptr());
+            if symbol.is_null() {
+                Err(last_error())
+            } else {
+                Ok(symbol)
+            }
+        }
+    }
+}
+
+/// A protocol loaded from a [`Library`](struct.Library.html), taking an argument of type `Arg`.
+///
+/// The library stays loaded as long as the handle exists.
+pub struct Protocol<Arg> {
+    name: String,
+    function: ProtocolFn,
+    library: Library,
+    arg: PhantomData<fn(&mut Arg)>,
+}
+impl<Arg> Protocol<Arg> {
+    /// Returns the symbol name of the protocol, e.g., for
+    /// [`ProtocolDesc::handshake`](../struct.ProtocolDesc.html#method.handshake).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the library containing the protocol.
+    pub fn library(&self) -> &Library {
+        &self.library
+    }
+
+    /// Returns the protocol function, to be passed to functions taking a
+    /// [`ProtocolFn`](../type.ProtocolFn.html). The function must not be called after the last
+    /// handle to the library is dropped.
+    pub fn function(&self) -> ProtocolFn {
+        self.function
+    }
+
+    /// Executes the protocol as a two-party Yao protocol, see
+    /// [`ProtocolDesc::exec_yao_protocol`](../struct.ProtocolDesc.html#method.exec_yao_protocol).
+    ///
+    /// # Errors
+    /// See [`ProtocolDesc::exec_yao_protocol`](../struct.ProtocolDesc.html#method.exec_yao_protocol).
+    ///
+    /// # Safety
+    /// Only the size and alignment of `Arg` were checked when loading the protocol. The protocol
+    /// must treat its argument as an `Arg`.
+    pub unsafe fn exec_yao_protocol(
+        &self,
+        pd: ProtocolDesc,
+        arg: &mut Arg,
+    ) -> Result<(), ExecError> {
+        pd.exec_yao_protocol(self.function, arg)
+    }
+}
+impl<Arg> Clone for Protocol<Arg> {
+    fn clone(&self) -> Self {
+        Protocol {
+            name: self.name.clone(),
+            function: self.function,
+            library: self.library.clone(),
+            arg: PhantomData,
+        }
+    }
+}
+impl<Arg> fmt::Debug for Protocol<Arg> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Protocol")
+            .field("name", &self.name)
+            .field("library", &self.library.path)
+            .finish()
+    }
+}
+
+/// Error returned by [`Compiler`](struct.Compiler.html).
+#[cfg(feature = "shared")]
+#[derive(Debug)]
+pub enum CompileError {
+    /// Accessing the cache directory or running the compiler failed
+    Io(io::Error),
+    /// The source could not be compiled or linked
+    Compile {
+        /// The errors reported by the compiler, as `file:line:column: error: message`
+        errors: Vec<String>,
+        /// The complete output of the compiler
+        output: String,
+    },
+    /// The compiled library could not be loaded
+    Load(LoadError),
+}
+#[cfg(feature = "shared")]
+impl Error for CompileError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            &CompileError::Io(ref e) => Some(e),
+            &CompileError::Load(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+#[cfg(feature = "shared")]
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &CompileError::Io(ref e) => write!(f, "Running the compiler failed: {}", e),
+            &CompileError::Compile {
+                ref errors,
+                ref output,
+            } => if errors.is_empty() {
+                write!(f, "Compilation failed:\n{}", output.trim_end())
+            } else {
+                write!(f, "Compilation failed:\n{}", errors.join("\n"))
+            },
+            &CompileError::Load(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+#[cfg(feature = "shared")]
+impl From<io::Error> for CompileError {
+    fn from(e: io::Error) -> Self {
+        CompileError::Io(e)
+    }
+}
+#[cfg(feature = "shared")]
+impl From<LoadError> for CompileError {
+    fn from(e: LoadError) -> Self {
+        CompileError::Load(e)
+    }
+}
+
+// Distinguishes concurrent compilations of the same source within a process
+#[cfg(feature = "shared")]
+static BUILD_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Compiles Obliv-C sources at runtime into [`Library`](struct.Library.html)s.
+///
+/// Sources are compiled by the `oblivcc` this crate was built with, using the include paths of
+/// [`compiler`](../fn.compiler.html), so the Obliv-C installation has to be present when the
+/// program runs. The compiled libraries are linked against the shared Obliv-C runtime and stored
+/// in a cache directory, under a name derived from a hash of the source, the compiler settings,
+/// and the versions of this crate and Obliv-C. Compiling the same source again returns the cached
+/// library.
+///
+/// Only available with the `shared` feature.
+///
+/// # Examples
+/// ```no_run
+/// # extern crate oblivc;
+/// # fn main() {
+/// use oblivc::dynamic::Compiler;
+///
+/// #[repr(C)]
+/// struct Args {
+///     input: i32,
+///     output: i8,
+/// }
+///
+/// let source = r#"
+///     #include <obliv.oh>
+///     #include <oblivc_dynamic.h>
+///
+///     typedef struct { int input; char output; } args_t;
+///
+///     void less(void* vargs) {
+///       args_t* args = vargs;
+///       obliv bool result = feedOblivInt(args->input, 1) < feedOblivInt(args->input, 2);
+///       revealOblivBool(&args->output, result, 0);
+///     }
+///     OBLIVC_PROTOCOL(less, args_t);
+/// "#;
+/// let compiler = Compiler::new("/tmp/oblivc-cache").flag("-O2");
+/// let library = unsafe { compiler.load(source) }.unwrap();
+/// let less = library.protocol::<Args>("less").unwrap();
+/// # }
+/// ```
+#[cfg(feature = "shared")]
+#[derive(Clone, Debug)]
+pub struct Compiler {
+    cache_dir: PathBuf,
+    includes: Vec<PathBuf>,
+    defines: Vec<(String, Option<String>)>,
+    flags: Vec<String>,
+}
+#[cfg(feature = "shared")]
+impl Compiler {
+    /// Returns a new [`Compiler`](struct.Compiler.html) that stores compiled libraries in
+    /// `cache_dir`. The directory is created when needed.
+    pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        Compiler {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            includes: Vec::new(),
+            defines: Vec::new(),
+            flags: Vec::new(),
+        }
+    }
+
+    /// Adds a directory to the include path.
+    pub fn include<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.includes.push(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Defines the preprocessor macro `name`, with `value` if given.
+    pub fn define(mut self, name: &str, value: Option<&str>) -> Self {
+        self.defines
+            .push((name.to_string(), value.map(|v| v.to_string())));
+        self
+    }
+
+    /// Passes an arbitrary `flag` to `oblivcc`, e.g., `-O2`.
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// Compiles `source` into a shared library and returns its path, or returns the path of the
+    /// cached library if `source` was compiled before with the same settings.
+    ///
+    /// # Errors
+    /// * If the compiler rejects the source, a [`CompileError::Compile`](enum.CompileError.html)
+    /// with the reported errors is returned. Their paths refer to the source as `source.oc`.
+    /// * If the cache directory cannot be written or the compiler cannot be run, a
+    /// [`CompileError::Io`](enum.CompileError.html) is returned.
+    pub fn compile(&self, source: &str) -> Result<PathBuf, CompileError> {
+        let key = self.key(source);
+        let library = self.cache_dir.join(format!("lib{}.so", key));
+        if library.exists() {
+            return Ok(library);
+        }
+        // build in a private directory and move the result into place, so that concurrent
+        // compilations never see incomplete libraries
+        let dir = self.cache_dir.join(format!(
+            "{}-{}-{}.tmp",
+            key,
+            process::id(),
+            BUILD_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir)?;
+        let result = self
+            .build(source, &dir)
+            .and_then(|built| fs::rename(&built, &library).map_err(CompileError::from));
+        let _ = fs::remove_dir_all(&dir);
+        result.map(|()| library)
+    }
+
+    /// [Compiles](#method.compile) `source` and loads the resulting library.
+    ///
+    /// # Errors
+    /// See [`compile`](#method.compile). If loading the library fails, a
+    /// [`CompileError::Load`](enum.CompileError.html) is returned.
+    ///
+    /// # Safety
+    /// See [`Library::open`](struct.Library.html#method.open).
+    pub unsafe fn load(&self, source: &str) -> Result<Library, CompileError> {
+        let path = self.compile(source)?;
+        Ok(Library::open(path)?)
+    }
+
+    // Returns the name of the cached library for `source`. Besides the settings, it covers the
+    // contents of all headers included by `source`, so that changing them invalidates the cache.
+    fn key(&self, source: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.field(source.as_bytes());
+        for include in &self.includes {
+            hasher.field(include.as_os_str().as_bytes());
+        }
+        for &(ref name, ref value) in &self.defines {
+            hasher.field(name.as_bytes());
+            hasher.field(value.as_ref().map_or(b"" as &[u8], |v| v.as_bytes()));
+        }
+        for flag in &self.flags {
+            hasher.field(flag.as_bytes());
+        }
+        hasher.field(c_compiler().as_bytes());
+        hasher.field(env!("CARGO_PKG_VERSION").as_bytes());
+        hasher.field(::libobliv_sys::OBLIVC_REVISION.as_bytes());
+        for header in self.dependencies(source) {
+            hasher.field(header.as_os_str().as_bytes());
+            hasher.field(&fs::read(&header).unwrap_or_default());
+        }
+        sha256::hex(&hasher.finish())
+    }
+
+    // Returns all headers included by `source`, as reported by the C preprocessor. If that
+    // fails, no headers are returned and compiling `source` reports the error.
+    fn dependencies(&self, source: &str) -> Vec<PathBuf> {
+        let mut command = Command::new(c_compiler());
+        for include in OBLIVC_INCLUDE.iter().chain(&self.includes) {
+            command.arg(format!("-I{}", include.display()));
+        }
+        command.args(build::define_args(&self.defines));
+        build::dependencies(command, Path::new("-"), source.as_bytes()).unwrap_or_default()
+    }
+
+    // Compiles `source` in `dir` and returns the path of the library.
+    fn build(&self, source: &str, dir: &Path) -> Result<PathBuf, CompileError> {
+        fs::write(dir.join("source.oc"), source)?;
+        let mut command = Command::new(OBLIVC_ROOT.join("bin/oblivcc"));
+        command.current_dir(dir).arg("-c").arg("-fPIC");
+        for include in OBLIVC_INCLUDE.iter().chain(&self.includes) {
+            command.arg(format!("-I{}", include.display()));
+        }
+        command
+            .args(build::define_args(&self.defines))
+            .args(&self.flags)
+            .arg("-o")
+            .arg("source.o")
+            .arg("source.oc");
+        check_output(command.output()?, dir)?;
+
+        let mut command = Command::new(c_compiler());
+        command.current_dir(dir);
+        build::link_shared_args(
+            &mut command,
+            Path::new("libprotocol.so"),
+            &[PathBuf::from("source.o")],
+        );
+        command.arg(concat!("-Wl,-rpath,", env!("DEP_OBLIV_LIB")));
+        check_output(command.output()?, dir)?;
+        Ok(dir.join("libprotocol.so"))
+    }
+}
+
+// Returns the C compiler used for linking libraries, which may be set via `CC` at runtime.
+#[cfg(feature = "shared")]
+fn c_compiler() -> OsString {
+    env::var_os("CC").unwrap_or_else(|| "cc".into())
+}
+
+// Turns a failed compiler run into a `CompileError::Compile`, with paths relative to `dir`.
+#[cfg(feature = "shared")]
+fn check_output(output: Output, dir: &Path) -> Result<(), CompileError> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    let errors = text
+        .lines()
+        .filter_map(Diagnostic::parse)
+        .filter(|d| d.level == Level::Error)
+        .map(|d| d.relative_to(dir).to_string())
+        .collect();
+    Err(CompileError::Compile {
+        errors,
+        output: text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[repr(C)]
+    struct Args {
+        input: i32,
+        output: i8,
+    }
+
+    fn layout(version: u32, size: usize, align: usize) -> Layout {
+        Layout {
+            version,
+            size,
+            align,
+            type_name: b"millionaire_args\0".as_ptr() as *const c_char,
+        }
+    }
+
+    #[test]
+    fn test_check_layout() {
+        assert!(check_layout::<Args>("millionaire", &layout(1, 8, 4)).is_ok());
+        match check_layout::<Args>("millionaire", &layout(1, 4, 4)) {
+            Err(LoadError::LayoutMismatch {
+                type_name,
+                found: (4, 4),
+                expected: (8, 4),
+                ..
+            }) => assert_eq!(type_name, "millionaire_args"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match check_layout::<Args>("millionaire", &layout(2, 8, 4)) {
+            Err(LoadError::UnsupportedLayout { version: 2, .. }) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_open_missing() {
+        match unsafe { Library::open("/nonexistent/libprotocol.so") } {
+            Err(LoadError::Open { path, .. }) => {
+                assert_eq!(path, PathBuf::from("/nonexistent/libprotocol.so"))
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_protocol_lookup() {
+        // the C library is loaded anyway, but contains no protocols
+        let library = unsafe { Library::open("libc.so.6") }.unwrap();
+        match library.protocol::<Args>("no_such_protocol") {
+            Err(LoadError::Symbol { name, .. }) => assert_eq!(name, "no_such_protocol"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match library.protocol::<Args>("getpid") {
+            Err(LoadError::MissingLayout(name)) => assert_eq!(name, "getpid"),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "shared")]
+    #[test]
+    fn test_compiler_key() {
+        let compiler = Compiler::new("/tmp/oblivc-cache");
+        let key = compiler.key("void f(void* arg) {}");
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, compiler.clone().key("void f(void* arg) {}"));
+        assert!(key != compiler.key("void g(void* arg) {}"));
+        assert!(key != compiler.clone().flag("-O2").key("void f(void* arg) {}"));
+        assert!(key != compiler.define("N", Some("1")).key("void f(void* arg) {}"));
+    }
+}
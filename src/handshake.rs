@@ -0,0 +1,280 @@
+use std::error::Error;
+use std::fmt;
+
+// Sent before every handshake message, followed by a format version.
+const MAGIC: &[u8; 8] = b"OBLIVCHS";
+const FORMAT_VERSION: u8 = 1;
+// Upper bound on the size of a handshake message we accept from the peer.
+const MAX_LEN: usize = 4096;
+
+/// The kind of protocol executed on a [`ProtocolDesc`](struct.ProtocolDesc.html).
+/// Currently, only two-party Yao protocols are supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProtocolMode {
+    /// Two-party Yao protocol, as run by
+    /// [`exec_yao_protocol`](struct.ProtocolDesc.html#method.exec_yao_protocol)
+    Yao,
+}
+impl ProtocolMode {
+    /// Returns the number of parties taking part in protocols of this mode.
+    pub fn num_parties(&self) -> usize {
+        match self {
+            &ProtocolMode::Yao => 2,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            &ProtocolMode::Yao => 1,
+        }
+    }
+}
+
+/// Error returned by [`ProtocolDesc::handshake`](struct.ProtocolDesc.html#method.handshake).
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// Sending or receiving handshake data failed
+    Transport,
+    /// The peer sent data that is not a valid handshake message, e.g., because it did not
+    /// request a handshake
+    Malformed,
+    /// Both parties use the same version of the handshake, but disagree on `field`
+    Mismatch {
+        /// Name of the field that differs
+        field: &'static str,
+        /// Value used by this party
+        local: String,
+        /// Value used by the peer
+        remote: String,
+    },
+}
+impl HandshakeError {
+    // The summary that starts the message of each variant.
+    fn message(&self) -> &str {
+        match self {
+            &HandshakeError::Transport => "Handshake failed to send or receive data",
+            &HandshakeError::Malformed => "Peer sent a malformed handshake message",
+            &HandshakeError::Mismatch { .. } => "Parties disagree on the protocol to execute",
+        }
+    }
+}
+impl Error for HandshakeError {}
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &HandshakeError::Mismatch {
+                field,
+                ref local,
+                ref remote,
+            } => write!(
+                f,
+                "{}: {} is `{}` locally, but `{}` at the peer",
+                self.message(),
+                field,
+                local,
+                remote
+            ),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+
+/// The information exchanged by both parties before executing a protocol.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Hello {
+    pub protocol: String,
+    pub mode: u8,
+    pub party: u8,
+    pub arg_size: u64,
+    pub arg_align: u64,
+    pub version: String,
+    pub oblivc_revision: String,
+}
+
+impl Hello {
+    /// Returns the message header, containing the length of the encoded body.
+    pub fn header(body: &[u8]) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(FORMAT_VERSION);
+        put_u32(&mut header, body.len() as u32);
+        header
+    }
+
+    /// Returns the length of the header created by `header`.
+    pub fn header_len() -> usize {
+        MAGIC.len() + 5
+    }
+
+    /// Parses a header returned by `header`, returning the length of the body.
+    pub fn parse_header(header: &[u8]) -> Option<usize> {
+        if header.len() != Hello::header_len()
+            || &header[..MAGIC.len()] != MAGIC
+            || header[MAGIC.len()] != FORMAT_VERSION
+        {
+            return None;
+        }
+        let len = get_u32(&header[MAGIC.len() + 1..]) as usize;
+        if len > MAX_LEN {
+            None
+        } else {
+            Some(len)
+        }
+    }
+
+    pub fn new(protocol: &str, mode: ProtocolMode, party: u8, size: usize, align: usize) -> Self {
+        Hello {
+            protocol: protocol.to_string(),
+            mode: mode.id(),
+            party,
+            arg_size: size as u64,
+            arg_align: align as u64,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            oblivc_revision: ::libobliv_sys::OBLIVC_REVISION.to_string(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_str(&mut buf, &self.protocol);
+        buf.push(self.mode);
+        buf.push(self.party);
+        put_u64(&mut buf, self.arg_size);
+        put_u64(&mut buf, self.arg_align);
+        put_str(&mut buf, &self.version);
+        put_str(&mut buf, &self.oblivc_revision);
+        buf
+    }
+
+    pub fn decode(mut buf: &[u8]) -> Option<Self> {
+        let buf = &mut buf;
+        let hello = Hello {
+            protocol: get_str(buf)?,
+            mode: take(buf, 1)?[0],
+            party: take(buf, 1)?[0],
+            arg_size: get_u64(take(buf, 8)?),
+            arg_align: get_u64(take(buf, 8)?),
+            version: get_str(buf)?,
+            oblivc_revision: get_str(buf)?,
+        };
+        if buf.is_empty() {
+            Some(hello)
+        } else {
+            None
+        }
+    }
+
+    /// Checks that `self` and the peer's message `remote` describe the same protocol.
+    pub fn check(&self, remote: &Hello) -> Result<(), HandshakeError> {
+        let mismatch = |field, local: &dyn fmt::Display, remote: &dyn fmt::Display| {
+            Err(HandshakeError::Mismatch {
+                field,
+                local: local.to_string(),
+                remote: remote.to_string(),
+            })
+        };
+        if self.protocol != remote.protocol {
+            mismatch("protocol", &self.protocol, &remote.protocol)
+        } else if self.mode != remote.mode {
+            mismatch("protocol mode", &self.mode, &remote.mode)
+        } else if self.party == remote.party {
+            // both parties must have different roles
+            mismatch("party", &self.party, &format!("{} as well", remote.party))
+        } else if self.arg_size != remote.arg_size {
+            mismatch("argument size", &self.arg_size, &remote.arg_size)
+        } else if self.arg_align != remote.arg_align {
+            mismatch("argument alignment", &self.arg_align, &remote.arg_align)
+        } else if self.version != remote.version {
+            mismatch("oblivc version", &self.version, &remote.version)
+        } else if self.oblivc_revision != remote.oblivc_revision {
+            mismatch(
+                "Obliv-C revision",
+                &self.oblivc_revision,
+                &remote.oblivc_revision,
+            )
+        } else {
+            Ok(())
+        }
+    }
+}
+
+pub(crate) fn put_u32(buf: &mut Vec<u8>, x: u32) {
+    buf.extend((0..4).map(|i| (x >> (8 * i)) as u8));
+}
+
+pub(crate) fn put_u64(buf: &mut Vec<u8>, x: u64) {
+    buf.extend((0..8).map(|i| (x >> (8 * i)) as u8));
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    put_u32(buf, s.len() as u32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+pub(crate) fn get_u32(buf: &[u8]) -> u32 {
+    buf[..4]
+        .iter()
+        .enumerate()
+        .fold(0, |x, (i, &b)| x | u32::from(b) << (8 * i))
+}
+
+pub(crate) fn get_u64(buf: &[u8]) -> u64 {
+    buf[..8]
+        .iter()
+        .enumerate()
+        .fold(0, |x, (i, &b)| x | u64::from(b) << (8 * i))
+}
+
+// Removes the first `n` bytes from `buf` and returns them.
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    if buf.len() < n {
+        return None;
+    }
+    let (head, tail) = buf.split_at(n);
+    *buf = tail;
+    Some(head)
+}
+
+fn get_str(buf: &mut &[u8]) -> Option<String> {
+    let len = get_u32(take(buf, 4)?) as usize;
+    String::from_utf8(take(buf, len)?.to_vec()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(party: u8) -> Hello {
+        Hello::new("millionaire", ProtocolMode::Yao, party, 8, 4)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let h = hello(1);
+        let body = h.encode();
+        let header = Hello::header(&body);
+        assert_eq!(header.len(), Hello::header_len());
+        assert_eq!(Hello::parse_header(&header), Some(body.len()));
+        assert_eq!(Hello::decode(&body), Some(h));
+        assert_eq!(Hello::decode(&body[1..]), None);
+        assert_eq!(Hello::parse_header(&body[..Hello::header_len()]), None);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(hello(1).check(&hello(2)).is_ok());
+        match hello(1).check(&hello(1)) {
+            Err(HandshakeError::Mismatch { field: "party", .. }) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+        let mut other = hello(2);
+        other.arg_size = 16;
+        match hello(1).check(&other) {
+            Err(HandshakeError::Mismatch {
+                field: "argument size",
+                local,
+                remote,
+            }) => assert_eq!((&local[..], &remote[..]), ("8", "16")),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
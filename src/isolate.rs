@@ -0,0 +1,353 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::panic::{self, AssertUnwindSafe};
+use std::process::ExitStatus;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use libc;
+
+use super::{Direction, ExecError};
+
+// Tags of the result message sent by the helper process
+const TAG_OK: u8 = 0;
+const TAG_IO: u8 = 1;
+const TAG_CANCELLED: u8 = 2;
+// any other error, sent as its message
+const TAG_OTHER: u8 = 3;
+
+// Kinds of I/O errors that are preserved when sent by the helper process. Others are reported
+// as `Other`, the first entry.
+const KINDS: &[io::ErrorKind] = &[
+    io::ErrorKind::Other,
+    io::ErrorKind::UnexpectedEof,
+    io::ErrorKind::WriteZero,
+    io::ErrorKind::BrokenPipe,
+    io::ErrorKind::ConnectionReset,
+    io::ErrorKind::ConnectionAborted,
+    io::ErrorKind::TimedOut,
+    io::ErrorKind::InvalidData,
+];
+
+// Exit code of the helper process if the execution panicked
+const PANIC_EXIT_CODE: c_int = 101;
+// How often to check whether the helper process terminated
+const POLL_TIMEOUT_MS: c_int = 10;
+
+/// Runs `f` in a forked helper process. `f` may modify the `len` bytes at `data`, which are
+/// copied back into this process if `f` returns successfully.
+///
+/// Standard error of the helper is captured and returned in `ExecError::ChildAborted` if the
+/// helper terminates without reporting a result, e.g., because Obliv-C called `exit` or `abort`.
+/// The helper is killed once `cancelled` is set, or if it does not terminate within `timeout`.
+pub(crate) unsafe fn run<F>(
+    data: *mut u8,
+    len: usize,
+    cancelled: &AtomicBool,
+    timeout: Option<Duration>,
+    f: F,
+) -> Result<(), ExecError>
+where
+    F: FnOnce() -> Result<(), ExecError>,
+{
+    let (result_read, result_write) = pipe()?;
+    let (stderr_read, stderr_write) = pipe()?;
+    let pid = libc::fork();
+    if pid < 0 {
+        return Err(ExecError::Isolation(io::Error::last_os_error()));
+    }
+    if pid == 0 {
+        drop(result_read);
+        drop(stderr_read);
+        child(data, len, f, result_write, stderr_write);
+    }
+    drop(result_write);
+    drop(stderr_write);
+
+    let mut pipes = [(result_read, Vec::new()), (stderr_read, Vec::new())];
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let status = collect(pid, &mut pipes, cancelled, deadline)?;
+    let [(_, ref message), (_, ref stderr)] = pipes;
+    if status.success() {
+        if let Some(result) = decode(message, data, len) {
+            return result;
+        }
+    }
+    Err(ExecError::ChildAborted {
+        status,
+        stderr: String::from_utf8_lossy(stderr).into_owned(),
+    })
+}
+
+// Reads from `pipes` until the process `pid` terminates and returns its exit status. Kills the
+// process once `cancelled` is set, or when `deadline` passes, in which case an error of kind
+// `TimedOut` is returned.
+//
+// Other threads may fork while the helper is running, in which case their children inherit
+// the write ends of the pipes. Hence the pipes are read without blocking until the helper
+// terminates, instead of waiting for the end of the pipes.
+fn collect(
+    pid: libc::pid_t,
+    pipes: &mut [(File, Vec<u8>); 2],
+    cancelled: &AtomicBool,
+    deadline: Option<Instant>,
+) -> Result<ExitStatus, ExecError> {
+    for (file, _) in pipes.iter() {
+        set_nonblocking(file)?;
+    }
+    let mut open = [true; 2];
+    let mut killed = false;
+    let mut timed_out = false;
+    loop {
+        if !killed && deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            timed_out = true;
+        }
+        if !killed && (timed_out || cancelled.load(Ordering::SeqCst)) {
+            unsafe { libc::kill(pid, libc::SIGKILL) };
+            killed = true;
+        }
+        let mut fds = [libc::pollfd {
+            fd: -1,
+            events: libc::POLLIN,
+            revents: 0,
+        }; 2];
+        for (i, (file, _)) in pipes.iter().enumerate() {
+            if open[i] {
+                fds[i].fd = file.as_raw_fd();
+            }
+        }
+        // wake up regularly to check whether the helper terminated
+        unsafe { libc::poll(fds.as_mut_ptr(), 2, POLL_TIMEOUT_MS) };
+        for (i, (file, buf)) in pipes.iter_mut().enumerate() {
+            if open[i] {
+                open[i] = drain(file, buf)?;
+            }
+        }
+        if let Some(status) = try_wait(pid)? {
+            // the helper cannot write anymore, so collect the remaining data
+            for (i, (file, buf)) in pipes.iter_mut().enumerate() {
+                if open[i] {
+                    drain(file, buf)?;
+                }
+            }
+            if timed_out {
+                return Err(ExecError::Isolation(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Helper process did not terminate in time",
+                )));
+            }
+            return Ok(status);
+        }
+    }
+}
+
+// Reads all currently available data from `file` into `buf`. Returns whether `file` is still
+// open.
+fn drain(file: &mut File, buf: &mut Vec<u8>) -> Result<bool, ExecError> {
+    let mut chunk = [0; 4096];
+    loop {
+        match file.read(&mut chunk) {
+            Ok(0) => return Ok(false),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(ExecError::Isolation(e)),
+        }
+    }
+}
+
+// Executes `f` in the helper process and reports the result. Never returns.
+unsafe fn child<F>(data: *mut u8, len: usize, f: F, mut result: File, stderr: File) -> !
+where
+    F: FnOnce() -> Result<(), ExecError>,
+{
+    if libc::dup2(stderr.as_raw_fd(), libc::STDERR_FILENO) < 0 {
+        libc::_exit(PANIC_EXIT_CODE);
+    }
+    drop(stderr);
+    // panics are reported by the panic hook on standard error; unwinding further would continue
+    // on the parent's copied stack, so encoding the result is guarded as well
+    let message = panic::catch_unwind(AssertUnwindSafe(|| match f() {
+        Ok(()) => {
+            let mut message = vec![TAG_OK];
+            message.extend_from_slice(::std::slice::from_raw_parts(data, len));
+            message
+        }
+        Err(e) => encode_error(&e),
+    }));
+    let message = match message {
+        Ok(message) => message,
+        Err(_) => libc::_exit(PANIC_EXIT_CODE),
+    };
+    let code = match result.write_all(&message) {
+        Ok(()) => 0,
+        Err(_) => 1,
+    };
+    // skip destructors and exit handlers, which belong to the parent's state
+    libc::_exit(code)
+}
+
+fn encode_error(e: &ExecError) -> Vec<u8> {
+    match e {
+        &ExecError::Io {
+            direction,
+            offset,
+            ref error,
+        } => {
+            let mut message = vec![TAG_IO];
+            message.push(match direction {
+                Direction::Send => 0,
+                Direction::Recv => 1,
+                Direction::Flush => 2,
+                Direction::Unknown => 3,
+            });
+            message.extend((0..8).map(|i| (offset >> (8 * i)) as u8));
+            let os_error = error.raw_os_error().unwrap_or(0);
+            message.extend((0..4).map(|i| (os_error >> (8 * i)) as u8));
+            message.push(
+                KINDS
+                    .iter()
+                    .position(|&kind| kind == error.kind())
+                    .unwrap_or(0) as u8,
+            );
+            message.extend_from_slice(error.to_string().as_bytes());
+            message
+        }
+        &ExecError::Cancelled => vec![TAG_CANCELLED],
+        // not returned by a single execution, but reported instead of being lost
+        e => {
+            let mut message = vec![TAG_OTHER];
+            message.extend_from_slice(e.to_string().as_bytes());
+            message
+        }
+    }
+}
+
+// Decodes a message written by `child`. Returns `None` if it is malformed.
+unsafe fn decode(message: &[u8], data: *mut u8, len: usize) -> Option<Result<(), ExecError>> {
+    match message.split_first() {
+        Some((&TAG_OK, payload)) if payload.len() == len => {
+            ptr::copy_nonoverlapping(payload.as_ptr(), data, len);
+            Some(Ok(()))
+        }
+        Some((&TAG_IO, payload)) if payload.len() >= 14 => {
+            let direction = match payload[0] {
+                0 => Direction::Send,
+                1 => Direction::Recv,
+                2 => Direction::Flush,
+                3 => Direction::Unknown,
+                _ => return None,
+            };
+            let offset = payload[1..9]
+                .iter()
+                .enumerate()
+                .fold(0, |x, (i, &b)| x | u64::from(b) << (8 * i));
+            let os_error = payload[9..13]
+                .iter()
+                .enumerate()
+                .fold(0, |x, (i, &b)| x | i32::from(b) << (8 * i));
+            let error = if os_error != 0 {
+                io::Error::from_raw_os_error(os_error)
+            } else {
+                let kind = *KINDS.get(payload[13] as usize)?;
+                io::Error::new(kind, String::from_utf8_lossy(&payload[14..]).into_owned())
+            };
+            Some(Err(ExecError::Io {
+                direction,
+                offset,
+                error,
+            }))
+        }
+        Some((&TAG_CANCELLED, payload)) if payload.is_empty() => Some(Err(ExecError::Cancelled)),
+        Some((&TAG_OTHER, payload)) => Some(Err(ExecError::Isolation(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(payload).into_owned(),
+        )))),
+        _ => None,
+    }
+}
+
+// Creates a pipe, returning the read and the write end.
+fn pipe() -> Result<(File, File), ExecError> {
+    let mut fds = [0 as c_int; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) < 0 {
+            return Err(ExecError::Isolation(io::Error::last_os_error()));
+        }
+        Ok((File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])))
+    }
+}
+
+fn set_nonblocking(file: &File) -> Result<(), ExecError> {
+    let fd = file.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(ExecError::Isolation(io::Error::last_os_error()));
+        }
+    }
+    Ok(())
+}
+
+// Returns the exit status of the process `pid` if it terminated.
+fn try_wait(pid: libc::pid_t) -> Result<Option<ExitStatus>, ExecError> {
+    let mut status: c_int = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+            0 => return Ok(None),
+            ret if ret > 0 => return Ok(Some(ExitStatus::from_raw(status))),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(ExecError::Isolation(error));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(e: ExecError) -> ExecError {
+        let message = encode_error(&e);
+        match unsafe { decode(&message, ptr::null_mut(), 0) } {
+            Some(Err(e)) => e,
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        match round_trip(ExecError::Io {
+            direction: Direction::Recv,
+            offset: 1234,
+            error: io::Error::new(io::ErrorKind::UnexpectedEof, "closed"),
+        }) {
+            ExecError::Io {
+                direction: Direction::Recv,
+                offset: 1234,
+                error,
+            } => {
+                assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+                assert_eq!(error.to_string(), "closed");
+            }
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        match round_trip(ExecError::Cancelled) {
+            ExecError::Cancelled => (),
+            other => panic!("Unexpected error: {:?}", other),
+        }
+        let error = ExecError::Isolation(io::Error::new(io::ErrorKind::Other, "nested"));
+        let message = error.to_string();
+        match round_trip(error) {
+            ExecError::Isolation(ref e) if e.to_string() == message => (),
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+}
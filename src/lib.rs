@@ -3,9 +3,39 @@
 //! Computation protocols as C-like programs.
 //! This wrapper allows to develop Rust programs that call Obliv-C protocols.
 //!
-//! If an Obliv-C installation is passed via the `OBLIVC_PATH` environment
-//! variable at build time, that installation is used.
-//! Otherwise, Obliv-C is built from source.
+//! If an Obliv-C source tree is passed via the `OBLIVC_PATH` environment
+//! variable at build time, that tree is used.
+//! Otherwise, the bundled Obliv-C sources are used.
+//! In both cases, Obliv-C is built in Cargo's output directory, so the sources
+//! are never modified and may be read-only.
+//!
+//! To use an Obliv-C that was already built, e.g., a system installation without
+//! OCaml tooling, additionally set `OBLIVC_PREBUILT=1`. Obliv-C is then not
+//! rebuilt; the build only checks that `bin/oblivcc`, `libobliv.a` (in `_build`
+//! or `lib`) and `obliv.h` (in `src/ext/oblivc` or `include`) exist below
+//! `OBLIVC_PATH`.
+//!
+//! When built from source, Obliv-C's runtime follows the optimization and debug
+//! settings of the Cargo profile, e.g., `-O0 -g` for debug builds. Sanitizers can
+//! be enabled by listing them in `OBLIVC_SANITIZE`, e.g.,
+//! `OBLIVC_SANITIZE=address,undefined`.
+//!
+//! Obliv-C's oblivious libraries, i.e., its ORAM variants and the data structures
+//! they are built from, are compiled into a companion library when the `extensions`
+//! feature is enabled. Their headers are then part of the include path of
+//! `oblivc::compiler()` and `oblivc::Build`, e.g., `#include <oram.oh>`. With
+//! `OBLIVC_PREBUILT`, their sources are expected in `test/oblivc/oram` below
+//! `OBLIVC_PATH`.
+//!
+//! With the `shared` feature, the runtime is linked as `libobliv.so`, and
+//! `oblivc::Build` produces protocol libraries `lib<name>.so` that link against it.
+//! This allows a process to load several protocol libraries at runtime without
+//! duplicating the runtime and its global state, e.g., via the [`dynamic`](dynamic/index.html)
+//! module. Both are placed in the build script's `OUT_DIR`, which has to be on the
+//! library search path when running the binary outside of Cargo.
+//! Obliv-C sources can also be compiled at runtime by
+//! [`dynamic::Compiler`](dynamic/struct.Compiler.html), which uses the Obliv-C
+//! installation this crate was built with.
 //!
 //! A small example using this library can be found
 //! [here](https://schoppmp.github.io/doc/oblivc-rust/test_oblivc/index.html).
@@ -18,6 +48,7 @@ extern crate bindgen;
 extern crate cc;
 #[macro_use]
 extern crate lazy_static;
+extern crate libc;
 extern crate libobliv_sys;
 
 use std::env;
@@ -26,24 +57,61 @@ use std::os::raw::{c_int, c_void};
 use std::mem;
 use std::ffi::{CString, NulError};
 use std::time::Duration;
-use std::thread;
 use std::fmt;
 use std::error::Error;
 use std::ops::Drop;
 use std::io::{Read, Write};
 use std::slice;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::io;
+use std::marker::PhantomData;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::os::unix::io::AsRawFd;
+use std::process::ExitStatus;
 use libobliv_sys::ProtocolTransport;
 
+mod build;
+mod cancel;
+mod child;
+mod diagnostic;
+pub mod dynamic;
+mod handshake;
+mod isolate;
+mod party;
+mod progress;
+mod retry;
+pub mod server;
+mod session;
+#[cfg(feature = "shared")]
+mod sha256;
+pub mod state;
+mod tcp;
+pub use build::Build;
+pub use cancel::CancelHandle;
+pub use child::ChildStream;
+pub use handshake::{HandshakeError, ProtocolMode};
+pub use party::Party;
+pub use progress::Progress;
+pub use retry::{Backoff, RetryPolicy};
+pub use session::{FrameError, Session};
+use state::{Connected, Disconnected, PartySet, PartyUnset};
+pub use tcp::{TcpMode, TcpStats};
+
 lazy_static! {
     // The root folder of the native Obliv-C installation
     static ref OBLIVC_ROOT : PathBuf = PathBuf::from(env!("DEP_OBLIV_ROOT"));
-    // A list of paths needed for compiling Obliv-C files
-    static ref OBLIVC_INCLUDE : Vec<PathBuf> =
-        env::split_paths(env!("DEP_OBLIV_INCLUDE")).collect();
+    // A list of paths needed for compiling Obliv-C files, including this crate's headers
+    static ref OBLIVC_INCLUDE : Vec<PathBuf> = env::split_paths(env!("DEP_OBLIV_INCLUDE"))
+        .chain(Some(PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/include"))))
+        .collect();
 }
 
 /// Returns a new [`cc::Build`](../cc/struct.Build.html) that uses `oblivcc` as compiler and
-/// includes all headers needed to compile Obliv-C sources.
+/// includes all headers needed to compile Obliv-C sources, as well as `oblivc_dynamic.h` for
+/// protocols loaded via [`dynamic`](dynamic/index.html).
 pub fn compiler() -> cc::Build {
     let mut builder = cc::Build::new();
     OBLIVC_INCLUDE.iter().fold(
@@ -59,34 +127,287 @@ pub fn bindings() -> bindgen::Builder {
     bindgen::builder().clang_args(OBLIVC_INCLUDE.iter().map(|p| format!("-I{}", p.display())))
 }
 
-/// Error returned by [`ProtocolDesc`](struct.ProtocolDesc.html) native Obliv-C connection methods.
-#[derive(Debug)]
-pub enum ConnectionError {
-    /// Indicates that either host or port contained null bytes
-    Nul(NulError),
-    /// Used to indicate other errors, for example timeouts
-    Other(&'static str),
-}
-impl std::error::Error for ConnectionError {
-    fn description(&self) -> &str {
+/// The kind of a [`ConnectionError`](struct.ConnectionError.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionErrorKind {
+    /// The peer actively refused the connection, e.g., because it is not listening yet
+    Refused,
+    /// A connection attempt timed out
+    Timeout,
+    /// The local address is already in use
+    AddrInUse,
+    /// The host name could not be resolved
+    Resolve,
+    /// Connecting was cancelled via a [`CancelHandle`](struct.CancelHandle.html)
+    Cancelled,
+    /// The handshake with the peer failed, see [`HandshakeError`](enum.HandshakeError.html)
+    Handshake,
+    /// An argument was invalid, e.g., a host or port containing null bytes
+    InvalidInput,
+    /// Any other failure
+    Other,
+}
+impl ConnectionErrorKind {
+    /// Returns whether a failure of this kind may go away by itself, so that retrying makes
+    /// sense. Unknown failures are considered transient.
+    pub fn is_transient(&self) -> bool {
         match self {
-            &ConnectionError::Nul(ref e) => e.description(),
-            &ConnectionError::Other(ref s) => s,
-            // &ConnectionError::Acc => "Accept call failed",
+            &ConnectionErrorKind::Refused
+            | &ConnectionErrorKind::Timeout
+            | &ConnectionErrorKind::Resolve
+            | &ConnectionErrorKind::Other => true,
+            &ConnectionErrorKind::AddrInUse
+            | &ConnectionErrorKind::Cancelled
+            | &ConnectionErrorKind::Handshake
+            | &ConnectionErrorKind::InvalidInput => false,
+        }
+    }
+}
+impl fmt::Display for ConnectionErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            &ConnectionErrorKind::Refused => "Connection refused",
+            &ConnectionErrorKind::Timeout => "Connection timed out",
+            &ConnectionErrorKind::AddrInUse => "Address already in use",
+            &ConnectionErrorKind::Resolve => "Failed to resolve address",
+            &ConnectionErrorKind::Cancelled => "Connecting was cancelled",
+            &ConnectionErrorKind::Handshake => "Handshake failed",
+            &ConnectionErrorKind::InvalidInput => "Invalid argument",
+            &ConnectionErrorKind::Other => "Connection failed",
+        })
+    }
+}
+
+/// Error returned by the connection methods of [`ProtocolDesc`](struct.ProtocolDesc.html) and by
+/// [`connect_stream`](fn.connect_stream.html).
+///
+/// The underlying cause, usually an `io::Error`, is available via `Error::source`.
+#[derive(Debug)]
+pub struct ConnectionError {
+    kind: ConnectionErrorKind,
+    peer: Option<String>,
+    attempts: usize,
+    source: Option<Box<dyn Error + Send + Sync>>,
+}
+impl ConnectionError {
+    // Returns an error of `kind` caused by `source`.
+    pub(crate) fn new<E>(kind: ConnectionErrorKind, source: E) -> Self
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        ConnectionError {
+            source: Some(source.into()),
+            ..ConnectionError::from(kind)
+        }
+    }
+
+    pub(crate) fn with_peer<A: ToString>(mut self, peer: A) -> Self {
+        self.peer = Some(peer.to_string());
+        self
+    }
+
+    pub(crate) fn with_attempts(mut self, attempts: usize) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    /// Returns the kind of this error.
+    pub fn kind(&self) -> ConnectionErrorKind {
+        self.kind
+    }
+
+    /// Returns whether retrying might succeed, see
+    /// [`ConnectionErrorKind::is_transient`](enum.ConnectionErrorKind.html#method.is_transient).
+    pub fn is_transient(&self) -> bool {
+        self.kind.is_transient()
+    }
+
+    /// Returns the address of the peer, if known.
+    pub fn peer(&self) -> Option<&str> {
+        self.peer.as_ref().map(|peer| &peer[..])
+    }
+
+    /// Returns the number of connection attempts made, or zero if the error occurred before
+    /// connecting, e.g., for invalid arguments.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    /// Returns the underlying OS error, if any.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        self.source.as_ref().and_then(|e| e.downcast_ref())
+    }
+}
+impl Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.source {
+            Some(ref e) => Some(&**e),
+            None => None,
         }
     }
 }
 impl fmt::Display for ConnectionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.description())
+        write!(f, "{}", self.kind)?;
+        if let Some(ref peer) = self.peer {
+            write!(f, " (peer {})", peer)?;
+        }
+        if self.attempts > 1 {
+            write!(f, " after {} attempts", self.attempts)?;
+        }
+        if let Some(ref source) = self.source {
+            write!(f, ": {}", source)?;
+        }
+        Ok(())
+    }
+}
+impl From<ConnectionErrorKind> for ConnectionError {
+    fn from(kind: ConnectionErrorKind) -> Self {
+        ConnectionError {
+            kind,
+            peer: None,
+            attempts: 0,
+            source: None,
+        }
+    }
+}
+impl From<io::Error> for ConnectionError {
+    fn from(e: io::Error) -> Self {
+        let kind = match e.kind() {
+            io::ErrorKind::ConnectionRefused => ConnectionErrorKind::Refused,
+            io::ErrorKind::TimedOut => ConnectionErrorKind::Timeout,
+            io::ErrorKind::AddrInUse => ConnectionErrorKind::AddrInUse,
+            io::ErrorKind::InvalidInput => ConnectionErrorKind::InvalidInput,
+            _ => ConnectionErrorKind::Other,
+        };
+        ConnectionError::new(kind, e)
     }
 }
 impl From<NulError> for ConnectionError {
     fn from(e: NulError) -> Self {
-        ConnectionError::Nul(e)
+        ConnectionError::new(ConnectionErrorKind::InvalidInput, e)
+    }
+}
+impl From<HandshakeError> for ConnectionError {
+    fn from(e: HandshakeError) -> Self {
+        ConnectionError::new(ConnectionErrorKind::Handshake, e)
+    }
+}
+
+/// The operation of a transport that failed during a protocol execution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Sending data to the other party
+    Send,
+    /// Receiving data from the other party
+    Recv,
+    /// Flushing data buffered for the other party
+    Flush,
+    /// An operation of a native transport, which Obliv-C does not report in detail
+    Unknown,
+}
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            &Direction::Send => "send",
+            &Direction::Recv => "receive",
+            &Direction::Flush => "flush",
+            &Direction::Unknown => "transport operation",
+        })
+    }
+}
+
+/// Error returned by [`ProtocolDesc::exec_yao_protocol`](struct.ProtocolDesc.html#method.exec_yao_protocol)
+/// and [`ProtocolDesc::run_yao_protocol`](struct.ProtocolDesc.html#method.run_yao_protocol).
+#[derive(Debug)]
+pub enum ExecError {
+    /// Indicates that the stream passed to
+    /// [`use_stream`](struct.ProtocolDesc.html#method.use_stream) or a native transport failed.
+    /// The protocol was aborted, so its outputs must not be used.
+    Io {
+        /// The operation that failed
+        direction: Direction,
+        /// The number of bytes successfully sent (or received, for `Direction::Recv`) over the
+        /// stream before the failure. Always zero for failures of a native transport.
+        offset: u64,
+        /// The error reported by the stream
+        error: io::Error,
+    },
+    /// Indicates that the helper process of an [`isolated`](struct.ProtocolDesc.html#method.isolated)
+    /// execution terminated without reporting a result, e.g., because Obliv-C called `exit` or
+    /// `abort`, or because the execution panicked.
+    ChildAborted {
+        /// The exit status of the helper process
+        status: ExitStatus,
+        /// Everything the helper process wrote to its standard error
+        stderr: String,
+    },
+    /// Indicates that the helper process of an [`isolated`](struct.ProtocolDesc.html#method.isolated)
+    /// execution could not be created or its result could not be read.
+    Isolation(io::Error),
+    /// Indicates that the execution was cancelled via a [`CancelHandle`](struct.CancelHandle.html).
+    Cancelled,
+}
+impl ExecError {
+    // The summary that starts the message of each variant.
+    fn message(&self) -> &str {
+        match self {
+            &ExecError::Io { .. } => "Communication with the other party failed",
+            &ExecError::ChildAborted { .. } => "Helper process terminated abnormally",
+            &ExecError::Isolation(_) => "Running the helper process failed",
+            &ExecError::Cancelled => "Protocol execution was cancelled",
+        }
+    }
+}
+impl std::error::Error for ExecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            &ExecError::Io { ref error, .. } => Some(error),
+            &ExecError::ChildAborted { .. } => None,
+            &ExecError::Isolation(ref error) => Some(error),
+            &ExecError::Cancelled => None,
+        }
+    }
+}
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ExecError::Io {
+                direction,
+                offset,
+                ref error,
+            } => write!(
+                f,
+                "{}: {} failed at byte {}: {}",
+                self.message(),
+                direction,
+                offset,
+                error
+            ),
+            &ExecError::ChildAborted {
+                status,
+                ref stderr,
+            } => write!(f, "{} ({}): {}", self.message(), status, stderr.trim_end()),
+            &ExecError::Isolation(ref error) => write!(f, "{}: {}", self.message(), error),
+            &ExecError::Cancelled => write!(f, "{}", self.message()),
+        }
     }
 }
 
+/// Connects a [`TcpStream`](https://doc.rust-lang.org/std/net/struct.TcpStream.html) to `addr`,
+/// retrying according to `policy`. The result can be passed to
+/// [`ProtocolDesc::use_stream`](struct.ProtocolDesc.html#method.use_stream).
+/// # Errors
+/// If no connection could be established within the limits of `policy`, or a failure is not
+/// [transient](struct.ConnectionError.html#method.is_transient), the error of the last attempt
+/// is returned.
+pub fn connect_stream<A: ToSocketAddrs>(
+    addr: A,
+    policy: &RetryPolicy,
+) -> Result<TcpStream, ConnectionError> {
+    policy.retry(|| tcp::connect(&addr))
+}
+
 /// Describes a protocol that can be executed via Obliv-C.
 /// Currently, only two-party Yao protocols are supported.
 ///
@@ -96,116 +417,440 @@ impl From<NulError> for ConnectionError {
 /// anything that implements
 /// [`Read`](https://doc.rust-lang.org/nightly/std/io/trait.Read.html) and
 /// [`Write`](https://doc.rust-lang.org/nightly/std/io/trait.Write.html) for communication by
-/// calling [`use_stream`](#method.use_stream).
-pub struct ProtocolDesc {
+/// calling [`use_stream`](#method.use_stream), or the standard input and output of the process
+/// via [`use_stdio`](#method.use_stdio).
+///
+/// The type parameters track whether the party and the transport were set (see the
+/// [`state`](state/index.html) module), so that only fully configured descriptors can execute
+/// protocols. They default to the configured state, which is what
+/// [`party`](#method.party) followed by, e.g., [`accept`](#method.accept) returns.
+pub struct ProtocolDesc<P = PartySet, T = Connected> {
+    inner: Inner,
+    state: PhantomData<(P, T)>,
+}
+// The part of a `ProtocolDesc` that does not depend on its configuration state, so that it can
+// be moved into a descriptor of another state.
+struct Inner {
     c: libobliv_sys::ProtocolDesc,
+    // whether `protocolAddSizeCheck` was applied to the transport
+    size_check: bool,
+    tcp_mode: TcpMode,
+    // socket owned by this crate, used in `TcpMode::KeepAlive`
+    socket: Option<TcpStream>,
+    // the native transport in `TcpMode::Profiled`, which may be wrapped in `c.trans`
+    profiled_trans: *mut ProtocolTransport,
+    tcp_stats: TcpStats,
+    // shared with the transport created by `use_stream`
+    stream_status: Option<Rc<TransportStatus>>,
+    // whether protocols are executed in a helper process
+    isolated: bool,
+    // time after which the helper process is killed
+    isolation_timeout: Option<Duration>,
+    cancel: CancelHandle,
+    progress: Option<progress::Reporter>,
 }
 pub type ProtocolFn = unsafe extern "C" fn(arg: *mut c_void);
-impl ProtocolDesc {
-    /// Returns a new [`ProtocolDesc`](#struct.ProtocolDesc)
+impl ProtocolDesc<PartyUnset, Disconnected> {
+    /// Returns a new [`ProtocolDesc`](#struct.ProtocolDesc) without party and transport
     pub fn new() -> Self {
         ProtocolDesc {
-            c: unsafe { mem::zeroed() },
+            inner: Inner {
+                c: unsafe { mem::zeroed() },
+                size_check: false,
+                tcp_mode: TcpMode::Plain,
+                socket: None,
+                profiled_trans: std::ptr::null_mut(),
+                tcp_stats: TcpStats::default(),
+                stream_status: None,
+                isolated: false,
+                isolation_timeout: None,
+                cancel: CancelHandle::default(),
+                progress: None,
+            },
+            state: PhantomData,
         }
     }
-
-    /// Sets the party id of this [`ProtocolDesc`](#struct.ProtocolDesc).
+}
+impl<T> ProtocolDesc<PartyUnset, T> {
+    /// Sets the party of this [`ProtocolDesc`](#struct.ProtocolDesc).
     /// # Panics
-    /// if `party` is not 1 or 2
+    /// if `party` does not take part in two-party Yao protocols, i.e., is neither the
+    /// [garbler](struct.Party.html#method.garbler) nor the
+    /// [evaluator](struct.Party.html#method.evaluator)
     /// # Examples
     /// ```should_panic
-    /// let mut pd = oblivc::protocol_desc().party(0); // panics
+    /// use oblivc::Party;
+    ///
+    /// let mut pd = oblivc::protocol_desc().party(Party::numbered(3)); // panics
     /// ```
-    pub fn party(mut self, party: c_int) -> Self {
-        if party != 1 && party != 2 {
-            panic!("Party must be either 1 or 2");
+    pub fn party(mut self, party: Party) -> ProtocolDesc<PartySet, T> {
+        if !party.is_valid_for(ProtocolMode::Yao) {
+            panic!("{} does not take part in {:?} protocols", party, ProtocolMode::Yao);
         }
         unsafe {
-            libobliv_sys::setCurrentParty(&mut self.c, party);
+            libobliv_sys::setCurrentParty(&mut self.inner.c, party.id());
         }
+        self.into_state()
+    }
+}
+impl<T> ProtocolDesc<PartySet, T> {
+    /// Returns the party set via [`party`](#method.party).
+    pub fn this_party(&self) -> Party {
+        Party::numbered(self.inner.c.thisParty)
+    }
+}
+impl<P, T> ProtocolDesc<P, T> {
+    // Moves this descriptor into another configuration state.
+    fn into_state<Q, U>(self) -> ProtocolDesc<Q, U> {
+        ProtocolDesc {
+            inner: self.inner,
+            state: PhantomData,
+        }
+    }
+
+    /// Selects the kind of native TCP transport created by [`accept`](#method.accept) and the
+    /// `connect` methods. Defaults to [`TcpMode::Plain`](enum.TcpMode.html).
+    pub fn tcp_mode(mut self, mode: TcpMode) -> Self {
+        self.inner.tcp_mode = mode;
         self
     }
 
+    /// Shorthand for [`tcp_mode(TcpMode::KeepAlive)`](#method.tcp_mode).
+    ///
+    /// The socket is created by this crate with TCP keep-alive probes enabled, so that
+    /// long-running sessions are not dropped by stateful firewalls and a dead peer is detected
+    /// after about two minutes (see [`TcpMode::KeepAlive`](enum.TcpMode.html)). It is passed to
+    /// Obliv-C's keep-alive transport and closed when this
+    /// [`ProtocolDesc`](#struct.ProtocolDesc) is dropped.
+    pub fn keep_alive(self) -> Self {
+        self.tcp_mode(TcpMode::KeepAlive)
+    }
+
+    /// Shorthand for [`tcp_mode(TcpMode::Profiled)`](#method.tcp_mode).
+    /// The profiling counters can be read via [`tcp_stats`](#method.tcp_stats).
+    pub fn profiled(self) -> Self {
+        self.tcp_mode(TcpMode::Profiled)
+    }
+
+    /// Executes protocols in a forked helper process, so that Obliv-C calling `exit` or `abort`,
+    /// e.g., on network failures or failed internal assertions, does not terminate the current
+    /// process. Instead, an [`ExecError::ChildAborted`](enum.ExecError.html) containing the exit
+    /// status and standard error of the helper is returned.
+    ///
+    /// The argument struct is copied back from the helper after a successful execution, so it
+    /// must be plain data: results written to memory it points to are lost. Likewise, the state
+    /// of streams, the [`tcp_stats`](#method.tcp_stats), and other side effects of the execution
+    /// are not visible in the current process.
+    ///
+    /// Note that forking a multi-threaded process only duplicates the calling thread. Locks held
+    /// by other threads at that time, e.g., of the memory allocator, remain locked in the helper,
+    /// which may then block forever. Use [`isolation_timeout`](#method.isolation_timeout) to
+    /// bound the time spent waiting for it.
+    pub fn isolated(mut self) -> Self {
+        self.inner.isolated = true;
+        self
+    }
+
+    /// Kills the helper process of an [`isolated`](#method.isolated) execution if it does not
+    /// terminate within `timeout`. The execution then fails with an
+    /// [`ExecError::Isolation`](enum.ExecError.html) of kind `TimedOut`. By default, there is no
+    /// timeout.
+    pub fn isolation_timeout(mut self, timeout: Duration) -> Self {
+        self.inner.isolation_timeout = Some(timeout);
+        self
+    }
+
+    /// Registers `callback` to be invoked with the [`Progress`](struct.Progress.html) of each
+    /// execution, once per `interval` and once after the execution finished. This works with all
+    /// transports.
+    ///
+    /// The periodic calls are made from a separate thread, so they continue while a party waits
+    /// for the other one, which makes them suitable for detecting stalled executions. The final
+    /// call is made on the thread executing the protocol. If the callback panics, the execution
+    /// is aborted at the next transport operation and the panic is resumed once control returns
+    /// to Rust. In [`isolated`](#method.isolated) mode, the callback is invoked in the helper
+    /// process.
+    ///
+    /// # Panics
+    /// if `interval` is zero
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # extern crate oblivc;
+    /// # #[repr(C)] struct Args { input: i32, output: i8 }
+    /// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+    /// # fn main() {
+    /// use std::time::Duration;
+    ///
+    /// let pd = oblivc::protocol_desc()
+    ///     .party(oblivc::Party::garbler())
+    ///     .on_progress(Duration::from_secs(1), |progress| {
+    ///         println!(
+    ///             "{:?}: {} bytes sent, {:?} gates",
+    ///             progress.elapsed(),
+    ///             progress.bytes_sent(),
+    ///             progress.gates()
+    ///         )
+    ///     })
+    ///     .accept("56744").unwrap();
+    /// let mut args = Args { input: 10, output: 0 };
+    /// unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap() };
+    /// # }
+    /// ```
+    pub fn on_progress<F>(mut self, interval: Duration, callback: F) -> Self
+    where
+        F: FnMut(&Progress) + Send + 'static,
+    {
+        if interval == Duration::from_secs(0) {
+            panic!("Progress interval must not be zero");
+        }
+        self.inner.progress = Some(progress::Reporter {
+            interval,
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    /// Returns a handle that cancels executions of this [`ProtocolDesc`](#struct.ProtocolDesc)
+    /// from another thread.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # extern crate oblivc;
+    /// # #[repr(C)] struct Args { input: i32, output: i8 }
+    /// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+    /// # fn main() {
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use oblivc::ExecError;
+    ///
+    /// let pd = oblivc::protocol_desc()
+    ///     .party(oblivc::Party::garbler())
+    ///     .accept("56742").unwrap();
+    /// let cancel = pd.cancel_handle();
+    /// thread::spawn(move || {
+    ///     thread::sleep(Duration::from_secs(60));
+    ///     cancel.cancel();
+    /// });
+    /// let mut args = Args { input: 10, output: 0 };
+    /// match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+    ///     Err(ExecError::Cancelled) => println!("Timed out"),
+    ///     result => result.unwrap(),
+    /// }
+    /// # }
+    /// ```
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.inner.cancel.clone()
+    }
+
+    /// Returns a handle to the profiling counters of the native TCP connection, or `None` if the
+    /// connection was not created in [`TcpMode::Profiled`](enum.TcpMode.html).
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # extern crate oblivc;
+    /// # #[repr(C)] struct Args { input: i32, output: i8 }
+    /// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+    /// # fn main() {
+    /// let mut args = Args { input: 10, output: 0 };
+    /// let pd = oblivc::protocol_desc()
+    ///     .party(oblivc::Party::garbler())
+    ///     .profiled()
+    ///     .accept("56738").unwrap();
+    /// let stats = pd.tcp_stats().unwrap();
+    /// unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap() };
+    /// println!("sent {} bytes in {} flushes", stats.bytes_sent(), stats.flush_count());
+    /// # }
+    /// ```
+    pub fn tcp_stats(&self) -> Option<TcpStats> {
+        if self.inner.profiled_trans.is_null() {
+            None
+        } else {
+            Some(self.inner.tcp_stats.clone())
+        }
+    }
+}
+impl<P> ProtocolDesc<P, Disconnected> {
+    // Hands a socket created by this crate to Obliv-C's keep-alive transport.
+    fn use_keep_alive_socket(&mut self, socket: TcpStream, is_client: bool) -> io::Result<()> {
+        tcp::set_keepalive(&socket)?;
+        unsafe {
+            libobliv_sys::protocolUseTcp2PKeepAlive(&mut self.inner.c, socket.as_raw_fd(), is_client);
+        }
+        self.inner.cancel.set_socket(Some(socket.as_raw_fd()));
+        self.inner.socket = Some(socket);
+        Ok(())
+    }
+
+    // Completes the setup of a native transport created by Obliv-C's accept or connect on
+    // `port`.
+    fn use_native_transport(&mut self, port: Option<u16>, is_client: bool) {
+        if self.inner.tcp_mode == TcpMode::Profiled {
+            self.inner.profiled_trans = self.inner.c.trans;
+        }
+        let socket = unsafe { tcp::native_socket(self.inner.c.trans, port, is_client) };
+        self.inner.cancel.set_socket(socket);
+    }
+
     /// Accepts an incoming connection on `port` using Obliv-C's networking stack.
     /// # Error
-    /// * If `port` contains a null byte, a
-    /// [`NulError`](https://doc.rust-lang.org/std/ffi/struct.NulError.html) is
-    /// returned.
-    /// * If the accept call fails, a
-    /// [`ConnectionError::Other`](enum.ConnectionError.html) is returned.
-    pub fn accept<P: Into<Vec<u8>>>(mut self, port: P) -> Result<Self, ConnectionError> {
+    /// * If `port` is not a valid port number, an error of kind
+    /// [`InvalidInput`](enum.ConnectionErrorKind.html) is returned.
+    /// * If `port` is used by another process, an error of kind
+    /// [`AddrInUse`](enum.ConnectionErrorKind.html) is returned.
+    /// * If accepting the connection fails otherwise, the error is of kind
+    /// [`Other`](enum.ConnectionErrorKind.html).
+    pub fn accept<Port: Into<Vec<u8>>>(
+        mut self,
+        port: Port,
+    ) -> Result<ProtocolDesc<P, Connected>, ConnectionError> {
         let port = CString::new(port)?;
-        match unsafe { libobliv_sys::protocolAcceptTcp2P(&mut self.c, port.as_ptr()) } {
-            0 => Ok(self),
-            _ => Err(ConnectionError::Other("Accept call failed")),
+        let number = tcp::port(&port)?;
+        let status = match self.inner.tcp_mode {
+            TcpMode::Plain | TcpMode::Profiled => {
+                let accept = match self.inner.tcp_mode {
+                    TcpMode::Profiled => libobliv_sys::protocolAcceptTcp2PProfiled,
+                    _ => libobliv_sys::protocolAcceptTcp2P,
+                };
+                unsafe { accept(&mut self.inner.c, port.as_ptr()) }
+            }
+            TcpMode::KeepAlive => {
+                let socket = tcp::accept(&port)?;
+                self.use_keep_alive_socket(socket, false)
+                    .map_err(|e| ConnectionError::new(ConnectionErrorKind::Other, e))?;
+                return Ok(self.into_state());
+            }
+        };
+        match status {
+            0 => {
+                self.use_native_transport(Some(number), false);
+                Ok(self.into_state())
+            }
+            _ => Err(io::Error::last_os_error().into()),
         }
     }
 
-    /// Tries to connect to `host:port` for `num_tries` times, waiting `sleep_time` between
-    /// attempts. If `num_tries` is `None`, this function tries forever.
+    /// Tries to connect to `host:port`, retrying according to `policy`. Only
+    /// [transient](struct.ConnectionError.html#method.is_transient) failures are retried.
     /// # Errors
-    /// * If either `host` or `port` contain a null byte, a
-    /// [`NulError`](https://doc.rust-lang.org/std/ffi/struct.NulError.html) is
-    /// returned.
-    /// * If no connection could be established after trying `num_tries` times, a
-    /// [`ConnectionError::Other`](enum.ConnectionError.html) is returned.
-    pub fn connect_loop<H: Into<Vec<u8>>, P: Into<Vec<u8>>>(
+    /// * If either `host` or `port` contain a null byte, an error of kind
+    /// [`InvalidInput`](enum.ConnectionErrorKind.html) is returned.
+    /// * If a [`CancelHandle`](struct.CancelHandle.html) of this descriptor is triggered, an
+    /// error of kind [`Cancelled`](enum.ConnectionErrorKind.html) is returned after the current
+    /// attempt.
+    /// * Otherwise, if no connection could be established within the limits of `policy`, the
+    /// error of the last attempt is returned.
+    pub fn connect_with<H: Into<Vec<u8>>, Port: Into<Vec<u8>>>(
         mut self,
         host: H,
-        port: P,
-        sleep_time: Duration,
-        num_tries: Option<usize>,
-    ) -> Result<Self, ConnectionError> {
+        port: Port,
+        policy: &RetryPolicy,
+    ) -> Result<ProtocolDesc<P, Connected>, ConnectionError> {
         let host = CString::new(host)?;
         let port = CString::new(port)?;
-        unsafe {
-            for i in 0.. {
-                let status =
-                    libobliv_sys::protocolConnectTcp2P(&mut self.c, host.as_ptr(), port.as_ptr());
-                if status == 0 {
-                    return Ok(self);
-                }
-                match num_tries {
-                    Some(n) => if i < n - 1 {
-                        thread::sleep(sleep_time);
-                    } else {
-                        break;
-                    },
-                    None => (),
+        let address = tcp::address(&host, &port)?;
+        let connect = match self.inner.tcp_mode {
+            TcpMode::KeepAlive => {
+                let socket = {
+                    let cancel = &self.inner.cancel;
+                    policy
+                        .retry(|| {
+                            if cancel.is_cancelled() {
+                                return Err(ConnectionErrorKind::Cancelled.into());
+                            }
+                            tcp::connect(&address[..])
+                        })
+                        .map_err(|e| e.with_peer(&address))?
                 };
+                self.use_keep_alive_socket(socket, true)
+                    .map_err(|e| ConnectionError::new(ConnectionErrorKind::Other, e))
+                    .map_err(|e| e.with_peer(&address))?;
+                return Ok(self.into_state());
             }
+            TcpMode::Plain => libobliv_sys::protocolConnectTcp2P,
+            TcpMode::Profiled => libobliv_sys::protocolConnectTcp2PProfiled,
+        };
+        {
+            let c = &mut self.inner.c;
+            let cancel = &self.inner.cancel;
+            policy
+                .retry(|| {
+                    if cancel.is_cancelled() {
+                        return Err(ConnectionErrorKind::Cancelled.into());
+                    }
+                    match unsafe { connect(c, host.as_ptr(), port.as_ptr()) } {
+                        0 => Ok(()),
+                        _ => Err(ConnectionError::from(io::Error::last_os_error())),
+                    }
+                })
+                .map_err(|e| e.with_peer(&address))?;
         }
-        Err(ConnectionError::Other("Connection attempt failed"))
+        // the port may also be a service name, which is only resolved by Obliv-C
+        self.use_native_transport(tcp::port(&port).ok(), true);
+        Ok(self.into_state())
+    }
+
+    /// Tries to connect to `host:port` for `num_tries` times, waiting `sleep_time` between
+    /// attempts. If `num_tries` is `None`, this function tries forever. At least one attempt is
+    /// made, even if `num_tries` is `Some(0)`.
+    /// # Errors
+    /// See [`connect_with`][con]
+    ///
+    /// [con]: #method.connect_with
+    pub fn connect_loop<H: Into<Vec<u8>>, Port: Into<Vec<u8>>>(
+        self,
+        host: H,
+        port: Port,
+        sleep_time: Duration,
+        num_tries: Option<usize>,
+    ) -> Result<ProtocolDesc<P, Connected>, ConnectionError> {
+        let policy = RetryPolicy::fixed(sleep_time);
+        let policy = match num_tries {
+            Some(n) => policy.max_tries(n),
+            None => policy,
+        };
+        self.connect_with(host, port, &policy)
     }
 
     /// Tries to connect to `host:port` in an infinite loop, waiting 100ms between attempts.
     /// # Errors
-    /// See [`connect_loop`][con]
+    /// See [`connect_with`][con]
     ///
-    /// [con]: #method.connect
-    pub fn connect<H: Into<Vec<u8>>, P: Into<Vec<u8>>>(
+    /// [con]: #method.connect_with
+    pub fn connect<H: Into<Vec<u8>>, Port: Into<Vec<u8>>>(
         self,
         host: H,
-        port: P,
-    ) -> Result<Self, ConnectionError> {
-        self.connect_loop(host, port, Duration::from_millis(100), None)
+        port: Port,
+    ) -> Result<ProtocolDesc<P, Connected>, ConnectionError> {
+        self.connect_with(host, port, &RetryPolicy::default())
     }
 
     /// Tries to connect to `host:port` once.
     /// # Errors
-    /// See [`connect_loop`][con]
+    /// See [`connect_with`][con]
     ///
-    /// [con]: #method.connect
-    pub fn connect_once<H: Into<Vec<u8>>, P: Into<Vec<u8>>>(
+    /// [con]: #method.connect_with
+    pub fn connect_once<H: Into<Vec<u8>>, Port: Into<Vec<u8>>>(
         self,
         host: H,
-        port: P,
-    ) -> Result<Self, ConnectionError> {
-        self.connect_loop(host, port, Duration::new(0, 0), Some(1))
+        port: Port,
+    ) -> Result<ProtocolDesc<P, Connected>, ConnectionError> {
+        self.connect_with(host, port, &RetryPolicy::once())
     }
 
     /// Uses `stream` for communication.
-    pub fn use_stream<'a, S: 'a + Read + Write>(mut self, stream: &mut S) -> Self {
+    ///
+    /// If a method of `stream` panics while a protocol is executed, the panic does not unwind
+    /// through Obliv-C. Instead, the failing call and all further communication fail, and the
+    /// panic is resumed once control returns to Rust.
+    pub fn use_stream<'a, S: 'a + Read + Write>(
+        mut self,
+        stream: &mut S,
+    ) -> ProtocolDesc<P, Connected> {
+        let status = Rc::new(TransportStatus {
+            cancel: self.inner.cancel.clone(),
+            ..TransportStatus::default()
+        });
         let boxed_trans = Box::new(StreamProtocolTransport {
             maxParties: 2,
             split: None,
@@ -213,54 +858,343 @@ impl ProtocolDesc {
             recv: Some(StreamProtocolTransport::<'a, S>::recv),
             flush: Some(StreamProtocolTransport::<'a, S>::flush),
             cleanup: Some(StreamProtocolTransport::<'a, S>::cleanup),
+            status: status.clone(),
             stream: stream,
         });
-        self.c.trans = Box::into_raw(boxed_trans) as *mut ProtocolTransport;
-        self
+        self.inner.c.trans = Box::into_raw(boxed_trans) as *mut ProtocolTransport;
+        self.inner.stream_status = Some(status);
+        self.into_state()
+    }
+
+    /// Uses the standard input and output of the current process for communication.
+    ///
+    /// This is useful for peer programs that are started by the other party, e.g., via a
+    /// [`ChildStream`](struct.ChildStream.html). Note that nothing else may be written to the
+    /// standard output while the protocol is running.
+    pub fn use_stdio(mut self) -> ProtocolDesc<P, Connected> {
+        unsafe {
+            libobliv_sys::protocolUseStdio(&mut self.inner.c);
+        }
+        self.into_state()
+    }
+}
+impl ProtocolDesc<PartySet, Connected> {
+    /// Exchanges a description of the protocol about to be executed with the other party and
+    /// checks that both agree on it. The description consists of the `protocol` identifier, the
+    /// `mode` of execution, the size and alignment of `Arg`, the party ids, as well as the
+    /// versions of this crate and of Obliv-C.
+    ///
+    /// After a successful handshake, Obliv-C's size checks (`protocolAddSizeCheck`) are enabled
+    /// on the transport, so that diverging executions are detected as early as possible.
+    /// Both parties have to call this function before executing the protocol.
+    ///
+    /// # Errors
+    /// * If both parties disagree on any part of the description, a
+    /// [`HandshakeError::Mismatch`](enum.HandshakeError.html) naming the first differing field
+    /// is returned.
+    /// * If the peer did not send a valid handshake message, a
+    /// [`HandshakeError::Malformed`](enum.HandshakeError.html) is returned.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # extern crate oblivc;
+    /// # #[repr(C)] struct Args { input: i32, output: i8 }
+    /// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+    /// # fn main() {
+    /// use oblivc::ProtocolMode;
+    ///
+    /// let mut args = Args { input: 10, output: 0 };
+    /// let pd = oblivc::protocol_desc()
+    ///     .party(oblivc::Party::garbler())
+    ///     .accept("56737").unwrap()
+    ///     .handshake::<Args>("millionaire", ProtocolMode::Yao).unwrap();
+    /// unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap() };
+    /// # }
+    /// ```
+    pub fn handshake<Arg>(
+        mut self,
+        protocol: &str,
+        mode: ProtocolMode,
+    ) -> Result<Self, HandshakeError> {
+        let hello = handshake::Hello::new(
+            protocol,
+            mode,
+            self.this_party().id() as u8,
+            mem::size_of::<Arg>(),
+            mem::align_of::<Arg>(),
+        );
+        let body = hello.encode();
+        let mut msg = handshake::Hello::header(&body);
+        msg.extend(body);
+        if !self.send_raw(&msg) {
+            return Err(HandshakeError::Transport);
+        }
+
+        let mut header = vec![0; handshake::Hello::header_len()];
+        if !self.recv_raw(&mut header) {
+            return Err(HandshakeError::Transport);
+        }
+        let len = handshake::Hello::parse_header(&header).ok_or(HandshakeError::Malformed)?;
+        let mut body = vec![0; len];
+        if !self.recv_raw(&mut body) {
+            return Err(HandshakeError::Transport);
+        }
+        let remote = handshake::Hello::decode(&body).ok_or(HandshakeError::Malformed)?;
+        hello.check(&remote)?;
+
+        if !self.inner.size_check {
+            unsafe {
+                libobliv_sys::protocolAddSizeCheck(&mut self.inner.c);
+            }
+            self.inner.size_check = true;
+        }
+        Ok(self)
+    }
+
+    // Sends `data` to the other party and flushes the transport. Returns whether both succeeded.
+    fn send_raw(&mut self, data: &[u8]) -> bool {
+        let trans = self.inner.c.trans;
+        let peer = 3 - self.inner.c.thisParty;
+        let ok = unsafe {
+            (*trans).send.unwrap()(trans, peer, data.as_ptr() as *const c_void, data.len()) >= 0
+                && (*trans).flush.unwrap()(trans) >= 0
+        };
+        self.inner.resume_transport_panic();
+        ok
+    }
+
+    // Fills `buf` with data received from the other party. Returns whether this succeeded.
+    fn recv_raw(&mut self, buf: &mut [u8]) -> bool {
+        let trans = self.inner.c.trans;
+        let peer = 3 - self.inner.c.thisParty;
+        let ok = unsafe {
+            (*trans).recv.unwrap()(trans, peer, buf.as_mut_ptr() as *mut c_void, buf.len()) >= 0
+        };
+        self.inner.resume_transport_panic();
+        ok
+    }
+
+    // Returns the first I/O error of a stream transport since the last call, if any.
+    fn take_transport_error(&self) -> Result<(), ExecError> {
+        let error = self.inner.stream_status
+            .as_ref()
+            .and_then(|status| status.error.borrow_mut().take());
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
     }
 
     /// Executes `f` with argument `arg` as a two-party Yao protocol
     ///
-    /// # Panics
-    /// * if not connected either via [`connect`][connect], [`connect_loop`][connect_loop],
-    /// [`connect_once`][connect_once], [`accept`][accept], or [`use_stream`][use_stream]
-    /// * if [`party`][party] was not called
+    /// # Errors
+    /// If the stream passed to [`use_stream`][use_stream] fails, an
+    /// [`ExecError::Io`](enum.ExecError.html) with the first error of the stream is returned.
+    /// Failures of native transports are returned as an `ExecError::Io` with
+    /// [`Direction::Unknown`](enum.Direction.html), as Obliv-C only reports that an error
+    /// occurred, except for the final flush of the outputs, which fails with
+    /// [`Direction::Flush`](enum.Direction.html). In all cases, the contents of `arg` are
+    /// unspecified.
+    ///
+    /// If the execution was cancelled via a [`CancelHandle`](struct.CancelHandle.html) before it
+    /// completed, an [`ExecError::Cancelled`](enum.ExecError.html) is returned.
     ///
     /// # Safety
     /// This function is unsafe, since calling arbitrary Obliv-C functions with arbitrary arguments
     /// may lead to undefined behavior. It is the caller's responsibility to ensure that the
     /// arguments match the function being executed and that `f` is safe.
     ///
-    /// [connect]: #method.connect
-    /// [connect_loop]: #method.connect_loop
-    /// [connect_once]: #method.connect_once
-    /// [accept]: #method.accept
     /// [use_stream]: #method.use_stream
-    /// [party]: #method.party
-    pub unsafe fn exec_yao_protocol<Arg>(mut self, f: ProtocolFn, arg: &mut Arg) {
-        if self.c.thisParty == 0 {
-            panic!("Party must be set before calling `exec_yao_protocol`");
+    pub unsafe fn exec_yao_protocol<Arg>(
+        mut self,
+        f: ProtocolFn,
+        arg: &mut Arg,
+    ) -> Result<(), ExecError> {
+        self.exec_yao(f, arg)
+    }
+
+    /// Executes `f` with argument `arg` as a two-party Yao protocol, keeping this
+    /// [`ProtocolDesc`](#struct.ProtocolDesc) and its connection open for further executions.
+    ///
+    /// Both parties have to execute the same sequence of protocols on their descriptors.
+    /// This avoids reconnecting for each execution, which can dominate the runtime of small
+    /// protocols.
+    ///
+    /// # Errors
+    /// See [`exec_yao_protocol`](#method.exec_yao_protocol). After an error, the parties are
+    /// likely out of sync, so the connection should not be used for further executions.
+    ///
+    /// # Safety
+    /// See [`exec_yao_protocol`](#method.exec_yao_protocol).
+    ///
+    /// # Examples
+    /// ```no_run
+    /// # extern crate oblivc;
+    /// # #[repr(C)] struct Args { input: i32, output: i8 }
+    /// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+    /// # fn main() {
+    /// let mut pd = oblivc::protocol_desc()
+    ///     .party(oblivc::Party::garbler())
+    ///     .accept("56741").unwrap();
+    /// for input in 0..1000 {
+    ///     let mut args = Args { input, output: 0 };
+    ///     unsafe { pd.run_yao_protocol(millionaire, &mut args).unwrap() };
+    /// }
+    /// # }
+    /// ```
+    pub unsafe fn run_yao_protocol<Arg>(
+        &mut self,
+        f: ProtocolFn,
+        arg: &mut Arg,
+    ) -> Result<(), ExecError> {
+        self.exec_yao(f, arg)
+    }
+
+    // Runs a single Yao protocol and resets the per-run state afterwards.
+    unsafe fn exec_yao<Arg>(&mut self, f: ProtocolFn, arg: &mut Arg) -> Result<(), ExecError> {
+        if self.inner.cancel.is_cancelled() {
+            return Err(ExecError::Cancelled);
+        }
+        let result = if self.inner.isolated {
+            let data = arg as *mut Arg as *mut u8;
+            let arg = arg as *mut Arg;
+            let cancel = self.inner.cancel.clone();
+            let timeout = self.inner.isolation_timeout;
+            isolate::run(data, mem::size_of::<Arg>(), cancel.cancelled_flag(), timeout, || {
+                self.exec_yao_here(f, &mut *arg)
+            })
+        } else {
+            self.exec_yao_here(f, arg)
+        };
+        // failures caused by the cancellation are reported as such, while a run that completed
+        // despite a late cancellation keeps its outputs
+        match result {
+            Err(_) if self.inner.cancel.is_cancelled() => Err(ExecError::Cancelled),
+            result => result,
+        }
+    }
+
+    // Runs a single Yao protocol in the current process.
+    //
+    // Obliv-C keeps little state in `c` between runs: `c.error` and `c.extra` are reset here,
+    // while the party, the transport and its byte counters deliberately persist across runs.
+    // Errors and panics recorded by the transport callbacks are taken, so they are reported
+    // only for the run that caused them.
+    unsafe fn exec_yao_here<Arg>(&mut self, f: ProtocolFn, arg: &mut Arg) -> Result<(), ExecError> {
+        let mut wrapper = self.inner
+            .progress
+            .take()
+            .map(|reporter| progress::ProgressTransport::new(&self.inner.c, reporter));
+        if let Some(ref mut wrapper) = wrapper {
+            self.inner.c.trans = wrapper.as_transport();
+        }
+        self.inner.c.error = 0;
+        libobliv_sys::execYaoProtocol(&mut self.inner.c, Some(f), arg as *mut _ as *mut c_void);
+        // Obliv-C frees its protocol-specific state at the end of the execution; make sure no
+        // dangling pointer to it survives until the next run.
+        self.inner.c.extra = std::ptr::null_mut();
+        // the last messages of this run might still be buffered by the transport
+        let trans = self.inner.c.trans;
+        let flush_error = match (*trans).flush.unwrap()(trans) {
+            status if status < 0 => Some(io::Error::last_os_error()),
+            _ => None,
+        };
+        let callback_panic = wrapper.and_then(|wrapper| {
+            let (inner, reporter, panic) = (*wrapper).finish();
+            self.inner.c.trans = inner;
+            self.inner.progress = Some(reporter);
+            panic
+        });
+        self.inner.update_tcp_stats();
+        self.inner.resume_transport_panic();
+        if let Some(payload) = callback_panic {
+            panic::resume_unwind(payload);
         }
-        if self.c.trans == std::ptr::null_mut() {
-            panic!("Connection must be established before calling `exec_yao_protocol`");
+        self.take_transport_error()?;
+        self.take_native_error()?;
+        match flush_error {
+            Some(error) => Err(ExecError::Io {
+                direction: Direction::Flush,
+                offset: 0,
+                error,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Returns an error if Obliv-C recorded a failure of the transport during the last run.
+    fn take_native_error(&mut self) -> Result<(), ExecError> {
+        match mem::replace(&mut self.inner.c.error, 0) {
+            0 => Ok(()),
+            _ => Err(ExecError::Io {
+                direction: Direction::Unknown,
+                offset: 0,
+                error: io::Error::new(io::ErrorKind::Other, "Native transport failed"),
+            }),
         }
-        libobliv_sys::execYaoProtocol(&mut self.c, Some(f), arg as *mut _ as *mut c_void);
     }
 }
 /// Alias for `ProtocolDesc::new()`
-pub fn protocol_desc() -> ProtocolDesc {
+pub fn protocol_desc() -> ProtocolDesc<PartyUnset, Disconnected> {
     ProtocolDesc::new()
 }
-impl Drop for ProtocolDesc {
+impl Inner {
+    // Copies the native profiling counters to `tcp_stats`.
+    fn update_tcp_stats(&mut self) {
+        if self.profiled_trans.is_null() {
+            return;
+        }
+        unsafe {
+            // the counters are read from `trans`, which might be wrapped by now
+            let mut native: libobliv_sys::ProtocolDesc = mem::zeroed();
+            native.trans = self.profiled_trans;
+            self.tcp_stats.update(
+                libobliv_sys::tcp2PBytesSent(&mut native) as usize,
+                libobliv_sys::tcp2PFlushCount(&mut native) as usize,
+            );
+        }
+    }
+
+    // Resumes a panic caught in one of the transport callbacks, if any.
+    fn resume_transport_panic(&self) {
+        let payload = self.stream_status
+            .as_ref()
+            .and_then(|status| status.panic.borrow_mut().take());
+        if let Some(payload) = payload {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+impl Drop for Inner {
     fn drop(&mut self) {
+        self.update_tcp_stats();
+        // surviving cancel handles must not shut down the socket once it is closed
+        self.cancel.set_socket(None);
         if self.c.trans != std::ptr::null_mut() {
             unsafe {
                 libobliv_sys::cleanupProtocol(&mut self.c);
             }
         }
+        // don't panic while unwinding, as that would abort
+        if !std::thread::panicking() {
+            self.resume_transport_panic();
+        }
     }
 }
 
+// State shared between a `StreamProtocolTransport` and its `ProtocolDesc`
+#[derive(Default)]
+struct TransportStatus {
+    // payload of a panic caught in a callback
+    panic: RefCell<Option<Box<dyn Any + Send>>>,
+    // the first I/O error of the current run
+    error: RefCell<Option<ExecError>>,
+    cancel: CancelHandle,
+    // number of bytes sent and received since the transport was created
+    sent: Cell<u64>,
+    received: Cell<u64>,
+}
+
 // Wraps a C ProtocolTransport struct that communicates via Read/Write traits
 #[repr(C)]
 #[allow(non_snake_case)]
@@ -281,20 +1215,63 @@ struct StreamProtocolTransport<'a, S: 'a + Read + Write> {
     >,
     pub flush: Option<unsafe extern "C" fn(t: *mut ProtocolTransport) -> c_int>,
     pub cleanup: Option<unsafe extern "C" fn(t: *mut ProtocolTransport)>,
+    pub status: Rc<TransportStatus>,
     pub stream: &'a mut S,
 }
 impl<'a, S: 'a + Read + Write> StreamProtocolTransport<'a, S> {
+    // Calls `f` on the stream of `t` and returns its result, or -1 if it fails.
+    // Panics must not unwind into Obliv-C, so they are caught and stored in `status`, like the
+    // first I/O error. After either, the stream is not used anymore in the current run.
+    unsafe fn with_stream<F>(t: *mut ProtocolTransport, direction: Direction, f: F) -> c_int
+    where
+        F: FnOnce(&mut S) -> io::Result<c_int>,
+    {
+        let trans = &mut *(t as *mut StreamProtocolTransport<'a, S>);
+        let status = &trans.status;
+        if status.panic.borrow().is_some() || status.error.borrow().is_some() {
+            return -1;
+        }
+        if status.cancel.is_cancelled() {
+            *status.error.borrow_mut() = Some(ExecError::Cancelled);
+            return -1;
+        }
+        let stream = &mut *trans.stream;
+        match panic::catch_unwind(AssertUnwindSafe(|| f(stream))) {
+            Ok(Ok(n)) => n,
+            Ok(Err(error)) => {
+                let offset = match direction {
+                    Direction::Recv => status.received.get(),
+                    Direction::Send | Direction::Flush | Direction::Unknown => status.sent.get(),
+                };
+                *status.error.borrow_mut() = Some(ExecError::Io {
+                    direction,
+                    offset,
+                    error,
+                });
+                -1
+            }
+            Err(payload) => {
+                *status.panic.borrow_mut() = Some(payload);
+                -1
+            }
+        }
+    }
     unsafe extern "C" fn send(
         t: *mut ProtocolTransport,
         _party: c_int,
         data: *const c_void,
         len: usize,
     ) -> c_int {
-        let stream = &mut ((*(t as *mut StreamProtocolTransport<'a, S>)).stream);
-        match stream.write_all(slice::from_raw_parts(data as *const u8, len)) {
-            Ok(()) => len as c_int,
-            Err(_) => -1,
+        let n = Self::with_stream(t, Direction::Send, |stream| {
+            stream
+                .write_all(slice::from_raw_parts(data as *const u8, len))
+                .map(|()| len as c_int)
+        });
+        if n >= 0 {
+            let trans = &*(t as *mut StreamProtocolTransport<'a, S>);
+            trans.status.sent.set(trans.status.sent.get() + len as u64);
         }
+        n
     }
     unsafe extern "C" fn recv(
         t: *mut ProtocolTransport,
@@ -302,21 +1279,27 @@ impl<'a, S: 'a + Read + Write> StreamProtocolTransport<'a, S> {
         data: *mut c_void,
         len: usize,
     ) -> c_int {
-        let stream = &mut ((*(t as *mut StreamProtocolTransport<'a, S>)).stream);
-        match stream.read_exact(slice::from_raw_parts_mut(data as *mut u8, len)) {
-            Ok(()) => len as c_int,
-            Err(_) => -1,
+        let n = Self::with_stream(t, Direction::Recv, |stream| {
+            stream
+                .read_exact(slice::from_raw_parts_mut(data as *mut u8, len))
+                .map(|()| len as c_int)
+        });
+        if n >= 0 {
+            let trans = &*(t as *mut StreamProtocolTransport<'a, S>);
+            trans.status.received.set(trans.status.received.get() + len as u64);
         }
+        n
     }
     unsafe extern "C" fn flush(t: *mut ProtocolTransport) -> c_int {
-        let stream = &mut ((*(t as *mut StreamProtocolTransport<'a, S>)).stream);
-        match stream.flush() {
-            Ok(()) => 0,
-            Err(_) => -1,
-        }
+        Self::with_stream(t, Direction::Flush, |stream| stream.flush().map(|()| 0))
     }
     unsafe extern "C" fn cleanup(t: *mut ProtocolTransport) {
-        Box::from_raw(t as *mut StreamProtocolTransport<'a, S>);
+        let trans = Box::from_raw(t as *mut StreamProtocolTransport<'a, S>);
+        let status = trans.status.clone();
+        // dropping the stream may panic as well
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(move || drop(trans))) {
+            *status.panic.borrow_mut() = Some(payload);
+        }
     }
 }
 
@@ -331,4 +1314,35 @@ mod tests {
     fn test_new_bindings() {
         let _ = bindings();
     }
+
+    // A stream that panics on every operation
+    struct PanickingStream;
+    impl Read for PanickingStream {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            panic!("read");
+        }
+    }
+    impl Write for PanickingStream {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            panic!("write");
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            panic!("flush");
+        }
+    }
+
+    #[test]
+    fn test_stream_panic() {
+        let result = panic::catch_unwind(|| {
+            protocol_desc()
+                .party(Party::garbler())
+                .use_stream(&mut PanickingStream)
+                .handshake::<()>("test", ProtocolMode::Yao)
+        });
+        // the first panic is resumed, further calls don't touch the stream
+        match result {
+            Err(payload) => assert_eq!(*payload.downcast::<&str>().unwrap(), "write"),
+            Ok(_) => panic!("Expected the stream's panic to be resumed"),
+        }
+    }
 }
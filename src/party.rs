@@ -0,0 +1,63 @@
+use std::fmt;
+use std::os::raw::c_int;
+
+use handshake::ProtocolMode;
+
+/// Identifies a party of a protocol execution.
+///
+/// Two-party protocols are run between a [`garbler`](#method.garbler) and an
+/// [`evaluator`](#method.evaluator). Protocols with more parties use
+/// [`numbered`](#method.numbered) ids.
+///
+/// # Examples
+/// ```
+/// use oblivc::{Party, ProtocolMode};
+///
+/// assert_eq!(Party::garbler(), Party::numbered(1));
+/// assert!(Party::evaluator().is_valid_for(ProtocolMode::Yao));
+/// assert!(!Party::numbered(3).is_valid_for(ProtocolMode::Yao));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Party(c_int);
+
+impl Party {
+    /// The party generating the garbled circuit in two-party protocols, i.e., party 1.
+    pub fn garbler() -> Self {
+        Party(1)
+    }
+
+    /// The party evaluating the garbled circuit in two-party protocols, i.e., party 2.
+    pub fn evaluator() -> Self {
+        Party(2)
+    }
+
+    /// The party with the given `id`. Ids start at 1.
+    /// # Panics
+    /// if `id` is less than 1
+    pub fn numbered(id: c_int) -> Self {
+        if id < 1 {
+            panic!("Party ids start at 1, got {}", id);
+        }
+        Party(id)
+    }
+
+    /// Returns the numeric id of this party, as used by Obliv-C.
+    pub fn id(&self) -> c_int {
+        self.0
+    }
+
+    /// Returns whether this party takes part in protocols of the given `mode`.
+    pub fn is_valid_for(&self, mode: ProtocolMode) -> bool {
+        self.0 as usize <= mode.num_parties()
+    }
+}
+
+impl fmt::Display for Party {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            1 => write!(f, "party 1 (garbler)"),
+            2 => write!(f, "party 2 (evaluator)"),
+            id => write!(f, "party {}", id),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use std::any::Any;
+use std::os::raw::{c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use libobliv_sys::{self, ProtocolTransport};
+
+/// The state of a running protocol execution, as reported to the callback registered via
+/// [`ProtocolDesc::on_progress`](struct.ProtocolDesc.html#method.on_progress).
+#[derive(Clone, Debug)]
+pub struct Progress {
+    bytes_sent: u64,
+    bytes_received: u64,
+    elapsed: Duration,
+    gates: Option<u64>,
+}
+impl Progress {
+    /// Returns the number of bytes sent to the other party during the current execution.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Returns the number of bytes received from the other party during the current execution.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Returns the time since the current execution started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the number of gates garbled or evaluated so far, or `None` if the protocol does not
+    /// expose it or has not set up its state yet.
+    pub fn gates(&self) -> Option<u64> {
+        self.gates
+    }
+}
+
+pub(crate) type Callback = Box<dyn FnMut(&Progress) + Send>;
+
+// A progress callback and how often it is invoked
+pub(crate) struct Reporter {
+    pub interval: Duration,
+    pub callback: Callback,
+}
+
+// The counters of an execution, updated by the transport callbacks
+#[derive(Default)]
+struct Counters {
+    bytes_sent: u64,
+    bytes_received: u64,
+    gates: Option<u64>,
+}
+
+// State shared between a `ProgressTransport` and its timer thread
+struct Shared {
+    counters: Mutex<Counters>,
+    callback: Mutex<Callback>,
+    start: Instant,
+    // payload of a panic caught in the callback
+    panic: Mutex<Option<Box<dyn Any + Send>>>,
+    panicked: AtomicBool,
+    // set once the execution finished, to stop the timer thread
+    finished: Mutex<bool>,
+    finished_cond: Condvar,
+}
+impl Shared {
+    fn report(&self) {
+        if self.panicked.load(Ordering::SeqCst) {
+            return;
+        }
+        let progress = {
+            let counters = self.counters.lock().unwrap();
+            Progress {
+                bytes_sent: counters.bytes_sent,
+                bytes_received: counters.bytes_received,
+                elapsed: self.start.elapsed(),
+                gates: counters.gates,
+            }
+        };
+        let mut callback = self.callback.lock().unwrap();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| (*callback)(&progress)));
+        if let Err(payload) = result {
+            *self.panic.lock().unwrap() = Some(payload);
+            self.panicked.store(true, Ordering::SeqCst);
+        }
+    }
+
+    // Invokes the callback every `interval` until the execution finished.
+    fn run_timer(&self, interval: Duration) {
+        let mut finished = self.finished.lock().unwrap();
+        let mut next = Instant::now() + interval;
+        while !*finished {
+            let now = Instant::now();
+            if now < next {
+                finished = self.finished_cond.wait_timeout(finished, next - now).unwrap().0;
+                continue;
+            }
+            drop(finished);
+            self.report();
+            next += interval;
+            finished = self.finished.lock().unwrap();
+        }
+    }
+}
+
+// Wraps the transport of a `ProtocolDesc` during an execution, counting the transferred bytes.
+// The callback of the `Reporter` is invoked by a timer thread, so that progress is also reported
+// while a party waits for the other.
+#[repr(C)]
+pub(crate) struct ProgressTransport {
+    cb: ProtocolTransport,
+    inner: *mut ProtocolTransport,
+    pd: *const libobliv_sys::ProtocolDesc,
+    interval: Duration,
+    shared: Arc<Shared>,
+    timer: Option<JoinHandle<()>>,
+}
+
+impl ProgressTransport {
+    /// Wraps the current transport of `pd` and starts reporting progress. The wrapper must be
+    /// removed via `finish` before the transport is cleaned up.
+    pub unsafe fn new(pd: *const libobliv_sys::ProtocolDesc, reporter: Reporter) -> Box<Self> {
+        let inner = (*pd).trans;
+        let shared = Arc::new(Shared {
+            counters: Mutex::new(Counters::default()),
+            callback: Mutex::new(reporter.callback),
+            start: Instant::now(),
+            panic: Mutex::new(None),
+            panicked: AtomicBool::new(false),
+            finished: Mutex::new(false),
+            finished_cond: Condvar::new(),
+        });
+        let timer = {
+            let shared = shared.clone();
+            let interval = reporter.interval;
+            thread::spawn(move || shared.run_timer(interval))
+        };
+        Box::new(ProgressTransport {
+            cb: ProtocolTransport {
+                maxParties: (*inner).maxParties,
+                // transports split off for other threads are not wrapped
+                split: if (*inner).split.is_some() {
+                    Some(Self::split)
+                } else {
+                    None
+                },
+                send: Some(Self::send),
+                recv: Some(Self::recv),
+                flush: Some(Self::flush),
+                // only called by `cleanupProtocol`, after the wrapper was removed
+                cleanup: None,
+            },
+            inner,
+            pd,
+            interval: reporter.interval,
+            shared,
+            timer: Some(timer),
+        })
+    }
+
+    /// Returns a pointer to the wrapper, to be used in place of the inner transport.
+    pub fn as_transport(&mut self) -> *mut ProtocolTransport {
+        &mut self.cb
+    }
+
+    /// Stops the timer thread, reports the final progress and returns the inner transport and
+    /// the reporter, as well as the payload of a panic caught in the callback.
+    pub fn finish(
+        mut self,
+    ) -> (
+        *mut ProtocolTransport,
+        Reporter,
+        Option<Box<dyn Any + Send>>,
+    ) {
+        self.stop_timer();
+        self.shared.report();
+        let (inner, interval, shared) = (self.inner, self.interval, self.shared.clone());
+        drop(self);
+        let shared = match Arc::try_unwrap(shared) {
+            Ok(shared) => shared,
+            Err(_) => unreachable!("the timer thread was joined"),
+        };
+        let reporter = Reporter {
+            interval,
+            callback: shared.callback.into_inner().unwrap(),
+        };
+        (inner, reporter, shared.panic.into_inner().unwrap())
+    }
+
+    fn stop_timer(&mut self) {
+        *self.shared.finished.lock().unwrap() = true;
+        self.shared.finished_cond.notify_all();
+        if let Some(timer) = self.timer.take() {
+            // panics of the callback are caught, so the thread does not fail
+            let _ = timer.join();
+        }
+    }
+
+    // Updates the counters after a transport operation. Returns `false` if the execution has
+    // to be aborted because the callback panicked.
+    unsafe fn update(&mut self, sent: usize, received: usize) -> bool {
+        let mut counters = self.shared.counters.lock().unwrap();
+        counters.bytes_sent += sent as u64;
+        counters.bytes_received += received as u64;
+        // the Yao protocol state is only available once set up by Obliv-C, and only on the
+        // executing thread
+        if !(*self.pd).extra.is_null() {
+            counters.gates = Some(libobliv_sys::yaoGateCount());
+        }
+        !self.shared.panicked.load(Ordering::SeqCst)
+    }
+
+    unsafe extern "C" fn send(
+        t: *mut ProtocolTransport,
+        party: c_int,
+        data: *const c_void,
+        len: usize,
+    ) -> c_int {
+        let trans = &mut *(t as *mut ProgressTransport);
+        // after a panic, the execution is aborted
+        if trans.shared.panicked.load(Ordering::SeqCst) {
+            return -1;
+        }
+        let ret = (*trans.inner).send.unwrap()(trans.inner, party, data, len);
+        let sent = if ret >= 0 { len } else { 0 };
+        if trans.update(sent, 0) {
+            ret
+        } else {
+            -1
+        }
+    }
+
+    unsafe extern "C" fn recv(
+        t: *mut ProtocolTransport,
+        party: c_int,
+        data: *mut c_void,
+        len: usize,
+    ) -> c_int {
+        let trans = &mut *(t as *mut ProgressTransport);
+        if trans.shared.panicked.load(Ordering::SeqCst) {
+            return -1;
+        }
+        let ret = (*trans.inner).recv.unwrap()(trans.inner, party, data, len);
+        let received = if ret >= 0 { len } else { 0 };
+        if trans.update(0, received) {
+            ret
+        } else {
+            -1
+        }
+    }
+
+    // Obliv-C passes the wrapper, so the inner transport has to be split instead.
+    unsafe extern "C" fn split(t: *mut ProtocolTransport) -> *mut ProtocolTransport {
+        let trans = &mut *(t as *mut ProgressTransport);
+        (*trans.inner).split.unwrap()(trans.inner)
+    }
+
+    unsafe extern "C" fn flush(t: *mut ProtocolTransport) -> c_int {
+        let trans = &mut *(t as *mut ProgressTransport);
+        if trans.shared.panicked.load(Ordering::SeqCst) {
+            return -1;
+        }
+        let ret = (*trans.inner).flush.unwrap()(trans.inner);
+        if trans.update(0, 0) {
+            ret
+        } else {
+            -1
+        }
+    }
+}
+impl Drop for ProgressTransport {
+    fn drop(&mut self) {
+        self.stop_timer();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem;
+
+    unsafe extern "C" fn split_inner(t: *mut ProtocolTransport) -> *mut ProtocolTransport {
+        t
+    }
+
+    fn reporter() -> Reporter {
+        Reporter {
+            interval: Duration::from_secs(1),
+            callback: Box::new(|_| ()),
+        }
+    }
+
+    #[test]
+    fn test_split_inner_transport() {
+        unsafe {
+            let mut inner: ProtocolTransport = mem::zeroed();
+            let mut pd: libobliv_sys::ProtocolDesc = mem::zeroed();
+            pd.trans = &mut inner;
+            // without a split function, the wrapper cannot be split either
+            let mut wrapper = ProgressTransport::new(&pd, reporter());
+            assert!((*wrapper.as_transport()).split.is_none());
+            drop(wrapper);
+
+            inner.split = Some(split_inner);
+            let mut wrapper = ProgressTransport::new(&pd, reporter());
+            let t = wrapper.as_transport();
+            assert_eq!((*t).split.unwrap()(t), &mut inner as *mut ProtocolTransport);
+        }
+    }
+
+    unsafe extern "C" fn recv_slowly(
+        _: *mut ProtocolTransport,
+        _: c_int,
+        _: *mut c_void,
+        len: usize,
+    ) -> c_int {
+        thread::sleep(Duration::from_millis(100));
+        len as c_int
+    }
+
+    #[test]
+    fn test_report_while_blocked() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reporter = {
+            let reports = reports.clone();
+            Reporter {
+                interval: Duration::from_millis(10),
+                callback: Box::new(move |progress| {
+                    reports.lock().unwrap().push(progress.bytes_received())
+                }),
+            }
+        };
+        unsafe {
+            let mut inner: ProtocolTransport = mem::zeroed();
+            inner.recv = Some(recv_slowly);
+            let mut pd: libobliv_sys::ProtocolDesc = mem::zeroed();
+            pd.trans = &mut inner;
+            let mut wrapper = ProgressTransport::new(&pd, reporter);
+            let t = wrapper.as_transport();
+            let mut buf = [0u8; 4];
+            assert_eq!((*t).recv.unwrap()(t, 1, buf.as_mut_ptr() as *mut c_void, 4), 4);
+            let (trans, _, panic) = wrapper.finish();
+            assert_eq!(trans, &mut inner as *mut ProtocolTransport);
+            assert!(panic.is_none());
+        }
+        let reports = reports.lock().unwrap();
+        // the timer reported while the receive was blocked, before any data arrived
+        assert!(reports.len() > 2, "{:?}", *reports);
+        assert_eq!(reports[0], 0);
+        assert_eq!(*reports.last().unwrap(), 4);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::ConnectionError;
+
+/// Strategy for computing the delay between two connection attempts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// Waits the same amount of time between all attempts.
+    Fixed(Duration),
+    /// Starts with `initial` and doubles the delay after each failed attempt, up to `max`.
+    Exponential {
+        /// Delay after the first failed attempt
+        initial: Duration,
+        /// Upper bound for the delay
+        max: Duration,
+    },
+}
+
+/// Describes how often and how long connection attempts are repeated.
+///
+/// A policy is made of a [`Backoff`](enum.Backoff.html) strategy, optional random jitter, and
+/// limits on the number of attempts and the total time spent connecting. Without limits, a policy
+/// retries forever.
+///
+/// # Examples
+/// ```
+/// use std::time::Duration;
+/// use oblivc::RetryPolicy;
+///
+/// // Wait 10ms, 20ms, 40ms, ... (but at most 1s) between attempts, randomize each delay,
+/// // and give up after 30 seconds.
+/// let policy = RetryPolicy::exponential(Duration::from_millis(10), Duration::from_secs(1))
+///     .jitter()
+///     .max_duration(Duration::from_secs(30));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    backoff: Backoff,
+    jitter: bool,
+    max_tries: Option<usize>,
+    max_duration: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Returns a policy that retries forever, waiting `delay` between attempts.
+    pub fn fixed(delay: Duration) -> Self {
+        RetryPolicy {
+            backoff: Backoff::Fixed(delay),
+            jitter: false,
+            max_tries: None,
+            max_duration: None,
+        }
+    }
+
+    /// Returns a policy that retries forever with exponentially growing delays between `initial`
+    /// and `max`.
+    pub fn exponential(initial: Duration, max: Duration) -> Self {
+        RetryPolicy {
+            backoff: Backoff::Exponential { initial, max },
+            ..RetryPolicy::fixed(Duration::new(0, 0))
+        }
+    }
+
+    /// Returns a policy that makes exactly one attempt.
+    pub fn once() -> Self {
+        RetryPolicy::fixed(Duration::new(0, 0)).max_tries(1)
+    }
+
+    /// Randomizes each delay uniformly between zero and the value computed by the backoff
+    /// strategy. This avoids many clients reconnecting in lockstep.
+    pub fn jitter(mut self) -> Self {
+        self.jitter = true;
+        self
+    }
+
+    /// Limits the number of attempts to `tries`. At least one attempt is always made, so `0` is
+    /// treated like `1`.
+    pub fn max_tries(mut self, tries: usize) -> Self {
+        self.max_tries = Some(tries);
+        self
+    }
+
+    /// Stops retrying once `duration` has passed since the first attempt. No attempt is started
+    /// if the following delay would exceed this limit.
+    pub fn max_duration(mut self, duration: Duration) -> Self {
+        self.max_duration = Some(duration);
+        self
+    }
+
+    /// Returns the delay to wait after `attempt` failed attempts, before applying jitter.
+    fn delay(&self, attempt: usize) -> Duration {
+        match self.backoff {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 1u32.checked_shl(attempt as u32 - 1).unwrap_or(0);
+                match initial.checked_mul(factor) {
+                    Some(delay) if factor != 0 && delay < max => delay,
+                    _ => max,
+                }
+            }
+        }
+    }
+
+    /// Calls `f` until it succeeds, fails permanently, or this policy gives up. The error of the
+    /// last attempt is returned.
+    pub(crate) fn retry<T, F>(&self, mut f: F) -> Result<T, ConnectionError>
+    where
+        F: FnMut() -> Result<T, ConnectionError>,
+    {
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let last_error = match f() {
+                Ok(t) => return Ok(t),
+                Err(e) => e.with_attempts(attempts),
+            };
+            if !last_error.is_transient() {
+                return Err(last_error);
+            }
+            if let Some(n) = self.max_tries {
+                if attempts >= n {
+                    return Err(last_error);
+                }
+            }
+            let mut delay = self.delay(attempts);
+            if self.jitter {
+                delay = scale(delay, random_fraction());
+            }
+            if let Some(max) = self.max_duration {
+                if start.elapsed() + delay > max {
+                    return Err(last_error);
+                }
+            }
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Tries to connect forever, waiting 100ms between attempts.
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::fixed(Duration::from_millis(100))
+    }
+}
+
+// Returns `d * fraction` for `fraction` in [0, 1].
+fn scale(d: Duration, fraction: f64) -> Duration {
+    let nanos = d.as_secs() as f64 * 1e9 + f64::from(d.subsec_nanos());
+    Duration::from_nanos((nanos * fraction) as u64)
+}
+
+// Returns a random number in [0, 1). `RandomState` is randomly seeded for each instance, which is
+// good enough for jitter and avoids depending on a random number generator.
+fn random_fraction() -> f64 {
+    let hash = RandomState::new().build_hasher().finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use ConnectionErrorKind;
+
+    #[test]
+    fn test_exponential_delay() {
+        let policy = RetryPolicy::exponential(Duration::from_millis(10), Duration::from_millis(50));
+        let delays: Vec<_> = (1..6).map(|i| policy.delay(i)).collect();
+        assert_eq!(
+            delays,
+            [10, 20, 40, 50, 50]
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(policy.delay(1000), Duration::from_millis(50));
+    }
+
+    fn refused() -> Result<(), ConnectionError> {
+        Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+    }
+
+    #[test]
+    fn test_max_tries() {
+        for &(tries, expected) in &[(0, 1), (1, 1), (3, 3)] {
+            let policy = RetryPolicy::fixed(Duration::new(0, 0)).max_tries(tries);
+            let mut calls = 0;
+            let err = policy
+                .retry(|| {
+                    calls += 1;
+                    refused()
+                })
+                .unwrap_err();
+            assert_eq!(calls, expected);
+            assert_eq!(err.attempts(), expected);
+            assert_eq!(err.kind(), ConnectionErrorKind::Refused);
+            assert!(err.io_error().is_some());
+        }
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let policy =
+            RetryPolicy::fixed(Duration::from_millis(20)).max_duration(Duration::from_millis(50));
+        let err = policy.retry(refused).unwrap_err();
+        // attempts at 0ms, 20ms and 40ms; allow for oversleeping
+        assert!(err.attempts() >= 2 && err.attempts() <= 3);
+    }
+
+    #[test]
+    fn test_permanent_error() {
+        let policy = RetryPolicy::fixed(Duration::new(0, 0));
+        let mut calls = 0;
+        let err = policy
+            .retry(|| -> Result<(), ConnectionError> {
+                calls += 1;
+                Err(io::Error::from(io::ErrorKind::AddrInUse).into())
+            })
+            .unwrap_err();
+        assert_eq!(calls, 1);
+        assert_eq!(err.kind(), ConnectionErrorKind::AddrInUse);
+    }
+
+    #[test]
+    fn test_jitter() {
+        let d = Duration::from_millis(100);
+        for _ in 0..100 {
+            assert!(scale(d, random_fraction()) <= d);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Serving an Obliv-C protocol to many clients.
+//!
+//! A [`Server`](struct.Server.html) owns a listener, accepts incoming connections, and runs the
+//! same protocol with each client on a bounded pool of worker threads. The server's input to each
+//! session is created by a callback when the connection is accepted, and the outcome of each
+//! session is reported back once it finishes.
+//!
+//! # Examples
+//! ```no_run
+//! # extern crate oblivc;
+//! # use std::os::raw::c_void;
+//! # unsafe extern "C" fn compare(_: *mut c_void) {}
+//! # fn main() {
+//! use std::net::TcpListener;
+//! use oblivc::server::Server;
+//!
+//! #[repr(C)]
+//! struct Args {
+//!     input: i32,
+//!     output: i8,
+//! }
+//!
+//! let listener = TcpListener::bind("0.0.0.0:56736").unwrap();
+//! let server = unsafe { Server::new(listener, compare) }.workers(8);
+//! let shutdown = server.shutdown_handle();
+//! server
+//!     .run(
+//!         |_peer| Args { input: 42, output: 0 },
+//!         |outcome| match outcome.result {
+//!             Ok(args) => println!("{:?}: {}", outcome.peer, args.output),
+//!             Err(e) => println!("{:?} failed: {}", outcome.peer, e),
+//!         },
+//!     )
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{self, UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::{protocol_desc, ExecError, Party, ProtocolFn, ProtocolMode};
+
+/// A source of incoming connections that can be used by a [`Server`](struct.Server.html).
+pub trait Listener {
+    /// The type of accepted connections
+    type Stream: Read + Write + Send + 'static;
+    /// The type of peer addresses
+    type Addr: fmt::Debug + Send + 'static;
+
+    /// Accepts a new connection. The returned stream must be in blocking mode.
+    fn accept(&self) -> io::Result<(Self::Stream, Self::Addr)>;
+
+    /// Moves this listener into or out of nonblocking mode.
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// Sets the read and write timeout of an accepted `stream`, so that a session fails instead
+    /// of blocking a worker forever if the client stops responding. The default implementation
+    /// does nothing, for streams that do not support timeouts.
+    fn set_timeout(&self, stream: &Self::Stream, timeout: Option<Duration>) -> io::Result<()> {
+        let _ = (stream, timeout);
+        Ok(())
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+    type Addr = SocketAddr;
+
+    fn accept(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        let (stream, addr) = TcpListener::accept(self)?;
+        stream.set_nonblocking(false)?;
+        Ok((stream, addr))
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_timeout(&self, stream: &TcpStream, timeout: Option<Duration>) -> io::Result<()> {
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+    type Addr = net::SocketAddr;
+
+    fn accept(&self) -> io::Result<(UnixStream, net::SocketAddr)> {
+        let (stream, addr) = UnixListener::accept(self)?;
+        stream.set_nonblocking(false)?;
+        Ok((stream, addr))
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixListener::set_nonblocking(self, nonblocking)
+    }
+
+    fn set_timeout(&self, stream: &UnixStream, timeout: Option<Duration>) -> io::Result<()> {
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)
+    }
+}
+
+/// Error describing why a session did not complete.
+#[derive(Debug)]
+pub enum SessionError {
+    /// The protocol execution panicked. Contains the panic payload.
+    Panicked(Box<dyn Any + Send>),
+    /// Communication with the client failed.
+    Exec(ExecError),
+}
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &SessionError::Panicked(ref payload) => {
+                let msg = payload
+                    .downcast_ref::<&str>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<String>().map(|s| &s[..]))
+                    .unwrap_or("unknown panic payload");
+                write!(f, "Protocol execution panicked: {}", msg)
+            }
+            &SessionError::Exec(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+impl Error for SessionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            &SessionError::Panicked(_) => None,
+            &SessionError::Exec(ref e) => Some(e),
+        }
+    }
+}
+
+/// The outcome of a single protocol session, as reported by [`Server::run`][run].
+///
+/// [run]: struct.Server.html#method.run
+#[derive(Debug)]
+pub struct SessionOutcome<Addr, A> {
+    /// Sequence number of the session, starting at zero
+    pub id: usize,
+    /// Address of the client
+    pub peer: Addr,
+    /// The server's argument struct after execution, or the reason the session failed
+    pub result: Result<A, SessionError>,
+}
+
+/// Requests a graceful shutdown of a [`Server`](struct.Server.html).
+///
+/// Handles can be cloned and sent to other threads.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+}
+impl ShutdownHandle {
+    /// Stops accepting new connections. Sessions that are already running or waiting for a
+    /// worker are completed before [`Server::run`][run] returns.
+    ///
+    /// [run]: struct.Server.html#method.run
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether a shutdown has been requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Accepts connections on a [`Listener`](trait.Listener.html) and runs a two-party Yao protocol
+/// with each client.
+///
+/// Sessions run on worker threads of the current process. They cannot be
+/// [`isolated`](../struct.ProtocolDesc.html#method.isolated), as forking a multi-threaded process
+/// is not safe.
+pub struct Server<L: Listener> {
+    listener: L,
+    f: ProtocolFn,
+    party: Party,
+    workers: usize,
+    poll_interval: Duration,
+    session_timeout: Option<Duration>,
+    shutdown: ShutdownHandle,
+}
+
+struct Job<S, Addr, A> {
+    id: usize,
+    stream: S,
+    peer: Addr,
+    arg: A,
+}
+
+impl<L: Listener> Server<L> {
+    /// Returns a new server that executes `f` for each connection accepted on `listener`. By
+    /// default, the server acts as the garbler and uses four worker threads.
+    ///
+    /// # Safety
+    /// This function is unsafe for the same reasons as
+    /// [`ProtocolDesc::exec_yao_protocol`](../struct.ProtocolDesc.html#method.exec_yao_protocol):
+    /// the caller must ensure that the argument structs passed to [`run`](#method.run) match `f`.
+    pub unsafe fn new(listener: L, f: ProtocolFn) -> Self {
+        Server {
+            listener,
+            f,
+            party: Party::garbler(),
+            workers: 4,
+            poll_interval: Duration::from_millis(50),
+            session_timeout: Some(Duration::from_secs(60)),
+            shutdown: ShutdownHandle {
+                flag: Arc::new(AtomicBool::new(false)),
+            },
+        }
+    }
+
+    /// Sets the party the server uses in each session.
+    /// # Panics
+    /// if `party` does not take part in two-party Yao protocols
+    pub fn party(mut self, party: Party) -> Self {
+        if !party.is_valid_for(ProtocolMode::Yao) {
+            panic!("{} does not take part in {:?} protocols", party, ProtocolMode::Yao);
+        }
+        self.party = party;
+        self
+    }
+
+    /// Sets the number of worker threads, i.e., the maximum number of concurrent sessions.
+    /// # Panics
+    /// if `workers` is zero
+    pub fn workers(mut self, workers: usize) -> Self {
+        if workers == 0 {
+            panic!("Server needs at least one worker");
+        }
+        self.workers = workers;
+        self
+    }
+
+    /// Sets how often the server checks for shutdown requests and finished sessions while
+    /// waiting for connections. Defaults to 50ms.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sets the read and write timeout of each session's stream (see
+    /// [`Listener::set_timeout`](trait.Listener.html#method.set_timeout)). A session fails if
+    /// its client does not respond within this time. Defaults to one minute; `None` disables
+    /// the timeout.
+    pub fn session_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.session_timeout = timeout;
+        self
+    }
+
+    /// Returns a handle that can be used to stop the server.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Runs the server until a shutdown is requested via a
+    /// [`ShutdownHandle`](struct.ShutdownHandle.html).
+    ///
+    /// For each accepted connection, `args` is called with the client's address to create the
+    /// server's argument struct. Connections are queued until a worker becomes available; if all
+    /// workers are busy and the queue is full, no further connections are accepted. A connection
+    /// that is waiting for a place in the queue when a shutdown is requested is closed.
+    /// `on_outcome` is called on the current thread for each finished session, also while waiting
+    /// for a worker.
+    ///
+    /// # Errors
+    /// If accepting a connection fails, the server stops accepting connections, waits for all
+    /// running sessions to complete, and returns the error. An error is also returned if all
+    /// workers terminated unexpectedly, so that connections can no longer be served.
+    pub fn run<A, F, G>(self, mut args: F, mut on_outcome: G) -> io::Result<()>
+    where
+        A: Send + 'static,
+        F: FnMut(&L::Addr) -> A,
+        G: FnMut(SessionOutcome<L::Addr, A>),
+    {
+        self.listener.set_nonblocking(true)?;
+        let (job_tx, job_rx) = mpsc::sync_channel::<Job<L::Stream, L::Addr, A>>(self.workers);
+        let job_rx = Arc::new(Mutex::new(job_rx));
+        let (outcome_tx, outcome_rx) = mpsc::channel();
+        let workers: Vec<_> = (0..self.workers)
+            .map(|_| {
+                let job_rx = job_rx.clone();
+                let outcome_tx = outcome_tx.clone();
+                let (f, party) = (self.f, self.party);
+                thread::spawn(move || worker(&job_rx, &outcome_tx, f, party))
+            })
+            .collect();
+        drop(outcome_tx);
+
+        let result = self.accept_loop(&job_tx, &outcome_rx, &mut args, &mut on_outcome);
+
+        // let the workers finish all queued jobs, then report the remaining outcomes
+        drop(job_tx);
+        for outcome in outcome_rx.iter() {
+            on_outcome(outcome);
+        }
+        for handle in workers {
+            // workers catch panics of the protocol, so this only fails on internal errors
+            handle.join().expect("Server worker panicked");
+        }
+        result
+    }
+
+    fn accept_loop<A, F, G>(
+        &self,
+        job_tx: &SyncSender<Job<L::Stream, L::Addr, A>>,
+        outcome_rx: &Receiver<SessionOutcome<L::Addr, A>>,
+        args: &mut F,
+        on_outcome: &mut G,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&L::Addr) -> A,
+        G: FnMut(SessionOutcome<L::Addr, A>),
+    {
+        let mut id = 0;
+        // an accepted connection that did not fit into the queue yet
+        let mut pending = None;
+        loop {
+            while let Ok(outcome) = outcome_rx.try_recv() {
+                on_outcome(outcome);
+            }
+            if let Some(job) = pending.take() {
+                match job_tx.try_send(job) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) if self.shutdown.is_shutdown() => break,
+                    Err(TrySendError::Full(job)) => {
+                        pending = Some(job);
+                        thread::sleep(self.poll_interval);
+                        continue;
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Other,
+                            "All server workers terminated",
+                        ))
+                    }
+                }
+            }
+            if self.shutdown.is_shutdown() {
+                break;
+            }
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    self.listener.set_timeout(&stream, self.session_timeout)?;
+                    let arg = args(&peer);
+                    pending = Some(Job {
+                        id,
+                        stream,
+                        peer,
+                        arg,
+                    });
+                    id += 1;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(self.poll_interval)
+                }
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::ConnectionAborted
+                        || e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn worker<S, Addr, A>(
+    jobs: &Mutex<Receiver<Job<S, Addr, A>>>,
+    outcomes: &mpsc::Sender<SessionOutcome<Addr, A>>,
+    f: ProtocolFn,
+    party: Party,
+) where
+    S: Read + Write,
+{
+    loop {
+        // only hold the lock while waiting for the next job
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let Job {
+            id,
+            mut stream,
+            peer,
+            mut arg,
+        } = job;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            let pd = protocol_desc().party(party).use_stream(&mut stream);
+            pd.exec_yao_protocol(f, &mut arg)
+        }));
+        let result = match result {
+            Ok(Ok(())) => Ok(arg),
+            Ok(Err(e)) => Err(SessionError::Exec(e)),
+            Err(payload) => Err(SessionError::Panicked(payload)),
+        };
+        let outcome = SessionOutcome {
+            id,
+            peer,
+            result,
+        };
+        if outcomes.send(outcome).is_err() {
+            return;
+        }
+    }
+}
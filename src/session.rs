@@ -0,0 +1,363 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use super::{protocol_desc, ExecError, HandshakeError, Party, ProtocolFn, ProtocolMode};
+use handshake::{get_u32, get_u64, put_u32, put_u64};
+
+// Kinds of frames sent over the stream of a session
+const FRAME_MESSAGE: u8 = 0;
+const FRAME_PROTOCOL: u8 = 1;
+// Length of a frame header: kind, phase, and payload length
+const HEADER_LEN: usize = 13;
+// Default upper bound on the size of a message we accept from the peer.
+const DEFAULT_MAX_MESSAGE_LEN: usize = 1 << 24;
+
+fn frame_name(kind: u8) -> &'static str {
+    match kind {
+        FRAME_MESSAGE => "message",
+        _ => "protocol",
+    }
+}
+
+/// Error returned by the methods of a [`Session`](struct.Session.html).
+///
+/// After an error, both parties are likely out of sync, so the session should not be used
+/// anymore.
+#[derive(Debug)]
+pub enum FrameError {
+    /// Reading from or writing to the stream failed.
+    Io(io::Error),
+    /// A message is longer than the limit set via
+    /// [`max_message_len`](struct.Session.html#method.max_message_len). Contains the length.
+    TooLarge(usize),
+    /// The peer sent data that is not a valid frame.
+    Malformed,
+    /// Both parties are in different phases, i.e., have executed a different number of
+    /// protocols.
+    PhaseMismatch {
+        /// The phase of this party
+        local: u64,
+        /// The phase of the peer
+        remote: u64,
+    },
+    /// The peer sent an application message while this party was about to execute a protocol,
+    /// or vice versa.
+    UnexpectedFrame {
+        /// What this party expected, either `"message"` or `"protocol"`
+        expected: &'static str,
+        /// What the peer sent
+        found: &'static str,
+    },
+    /// The handshake preceding a protocol execution failed.
+    Handshake(HandshakeError),
+    /// The protocol execution failed.
+    Exec(ExecError),
+}
+impl FrameError {
+    // The summary that starts the message of each variant. Wrapped errors are displayed as is.
+    fn message(&self) -> &str {
+        match self {
+            &FrameError::Io(_) => "Communication with the other party failed",
+            &FrameError::TooLarge(_) => "Message exceeds the maximum length",
+            &FrameError::Malformed => "Received an invalid frame",
+            &FrameError::PhaseMismatch { .. } => "Parties are in different phases",
+            &FrameError::UnexpectedFrame { .. } => "Received an unexpected frame",
+            &FrameError::Handshake(_) => "Handshake failed",
+            &FrameError::Exec(_) => "Protocol execution failed",
+        }
+    }
+}
+impl Error for FrameError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            &FrameError::Io(ref e) => Some(e),
+            &FrameError::Handshake(ref e) => Some(e),
+            &FrameError::Exec(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &FrameError::Io(ref e) => write!(f, "{}: {}", self.message(), e),
+            &FrameError::TooLarge(len) => write!(f, "{} ({} bytes)", self.message(), len),
+            &FrameError::PhaseMismatch { local, remote } => write!(
+                f,
+                "{}: phase {} locally, but {} at the peer",
+                self.message(),
+                local,
+                remote
+            ),
+            &FrameError::UnexpectedFrame { expected, found } => write!(
+                f,
+                "{}: expected {}, but the peer sent a {}",
+                self.message(),
+                expected,
+                found
+            ),
+            &FrameError::Handshake(ref e) => write!(f, "{}", e),
+            &FrameError::Exec(ref e) => write!(f, "{}", e),
+            _ => write!(f, "{}", self.message()),
+        }
+    }
+}
+impl From<io::Error> for FrameError {
+    fn from(e: io::Error) -> Self {
+        FrameError::Io(e)
+    }
+}
+impl From<HandshakeError> for FrameError {
+    fn from(e: HandshakeError) -> Self {
+        FrameError::Handshake(e)
+    }
+}
+impl From<ExecError> for FrameError {
+    fn from(e: ExecError) -> Self {
+        FrameError::Exec(e)
+    }
+}
+
+/// Wraps a stream to exchange application messages with the other party between protocol
+/// executions.
+///
+/// Messages are framed, so that both parties agree on their boundaries. The session counts the
+/// protocols executed on it in *phases*: each message and each protocol execution is tagged
+/// with the current phase, and the receiving side checks that it matches its own. Hence a party
+/// that skipped a message or a protocol is detected instead of desynchronizing the stream.
+///
+/// Each protocol execution is preceded by a [`handshake`](struct.ProtocolDesc.html#method.handshake).
+///
+/// # Examples
+/// ```no_run
+/// # extern crate oblivc;
+/// # #[repr(C)] struct Args { input: i32, output: i8 }
+/// # unsafe extern "C" fn millionaire(_: *mut std::os::raw::c_void) {}
+/// # fn main() {
+/// use std::net::TcpStream;
+/// use oblivc::{Party, Session};
+///
+/// let stream = TcpStream::connect("localhost:56747").unwrap();
+/// let mut session = Session::new(stream, Party::evaluator());
+/// session.send_message(b"job 42").unwrap();
+/// let size = session.recv_message().unwrap();
+/// let mut args = Args { input: size.len() as i32, output: 0 };
+/// unsafe {
+///     session
+///         .exec_yao_protocol("millionaire", millionaire, &mut args)
+///         .unwrap()
+/// };
+/// # }
+/// ```
+pub struct Session<S: Read + Write> {
+    stream: S,
+    party: Party,
+    phase: u64,
+    max_message_len: usize,
+}
+impl<S: Read + Write> Session<S> {
+    /// Returns a new session on `stream`, in which this process acts as `party`.
+    /// # Panics
+    /// if `party` does not take part in two-party Yao protocols
+    pub fn new(stream: S, party: Party) -> Self {
+        if !party.is_valid_for(ProtocolMode::Yao) {
+            panic!(
+                "{} does not take part in {:?} protocols",
+                party,
+                ProtocolMode::Yao
+            );
+        }
+        Session {
+            stream,
+            party,
+            phase: 0,
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+        }
+    }
+
+    /// Sets the maximum length of messages accepted from the peer. Defaults to 16 MiB.
+    pub fn max_message_len(mut self, len: usize) -> Self {
+        self.max_message_len = len;
+        self
+    }
+
+    /// Returns the party of this process.
+    pub fn party(&self) -> Party {
+        self.party
+    }
+
+    /// Returns the number of protocols executed successfully on this session.
+    pub fn phase(&self) -> u64 {
+        self.phase
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Sends `data` as a single message to the other party.
+    /// # Errors
+    /// * If `data` is longer than 4 GiB, a [`FrameError::TooLarge`](enum.FrameError.html) is
+    /// returned.
+    /// * If writing to the stream fails, a [`FrameError::Io`](enum.FrameError.html) is returned.
+    pub fn send_message(&mut self, data: &[u8]) -> Result<(), FrameError> {
+        if data.len() > u32::MAX as usize {
+            return Err(FrameError::TooLarge(data.len()));
+        }
+        self.write_frame(FRAME_MESSAGE, data)
+    }
+
+    /// Receives the next message sent by the other party via
+    /// [`send_message`](#method.send_message).
+    /// # Errors
+    /// * If the peer is in a different phase, a
+    /// [`FrameError::PhaseMismatch`](enum.FrameError.html) is returned.
+    /// * If the peer is about to execute a protocol instead, a
+    /// [`FrameError::UnexpectedFrame`](enum.FrameError.html) is returned.
+    /// * If the message is longer than [`max_message_len`](#method.max_message_len), a
+    /// [`FrameError::TooLarge`](enum.FrameError.html) is returned.
+    /// * If reading from the stream fails, a [`FrameError::Io`](enum.FrameError.html) is
+    /// returned.
+    pub fn recv_message(&mut self) -> Result<Vec<u8>, FrameError> {
+        self.read_frame(FRAME_MESSAGE)
+    }
+
+    /// Executes `f` with argument `arg` as a two-party Yao protocol on the stream of this
+    /// session, after checking that the other party is in the same phase and executes the same
+    /// `protocol`. Afterwards, the session moves to the next phase.
+    ///
+    /// # Errors
+    /// * If the other party is in a different phase or sends a message instead, the errors
+    /// described for [`recv_message`](#method.recv_message) are returned.
+    /// * If the [handshake](struct.ProtocolDesc.html#method.handshake) fails, a
+    /// [`FrameError::Handshake`](enum.FrameError.html) is returned.
+    /// * If the execution fails, a [`FrameError::Exec`](enum.FrameError.html) is returned.
+    ///
+    /// # Safety
+    /// See [`ProtocolDesc::exec_yao_protocol`](struct.ProtocolDesc.html#method.exec_yao_protocol).
+    pub unsafe fn exec_yao_protocol<Arg>(
+        &mut self,
+        protocol: &str,
+        f: ProtocolFn,
+        arg: &mut Arg,
+    ) -> Result<(), FrameError> {
+        self.write_frame(FRAME_PROTOCOL, &[])?;
+        self.read_frame(FRAME_PROTOCOL)?;
+        protocol_desc()
+            .party(self.party)
+            .use_stream(&mut self.stream)
+            .handshake::<Arg>(protocol, ProtocolMode::Yao)?
+            .exec_yao_protocol(f, arg)?;
+        self.phase += 1;
+        Ok(())
+    }
+
+    fn write_frame(&mut self, kind: u8, payload: &[u8]) -> Result<(), FrameError> {
+        let mut header = vec![kind];
+        put_u64(&mut header, self.phase);
+        put_u32(&mut header, payload.len() as u32);
+        self.stream.write_all(&header)?;
+        self.stream.write_all(payload)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
+    // Reads the next frame and returns its payload, checking that it is of the `expected` kind
+    // and belongs to the current phase.
+    fn read_frame(&mut self, expected: u8) -> Result<Vec<u8>, FrameError> {
+        let mut header = [0; HEADER_LEN];
+        self.stream.read_exact(&mut header)?;
+        let kind = header[0];
+        if kind != FRAME_MESSAGE && kind != FRAME_PROTOCOL {
+            return Err(FrameError::Malformed);
+        }
+        let phase = get_u64(&header[1..]);
+        if phase != self.phase {
+            return Err(FrameError::PhaseMismatch {
+                local: self.phase,
+                remote: phase,
+            });
+        }
+        if kind != expected {
+            return Err(FrameError::UnexpectedFrame {
+                expected: frame_name(expected),
+                found: frame_name(kind),
+            });
+        }
+        let len = get_u32(&header[9..]) as usize;
+        if len > self.max_message_len {
+            return Err(FrameError::TooLarge(len));
+        }
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+
+    fn pair() -> (Session<UnixStream>, Session<UnixStream>) {
+        let (a, b) = UnixStream::pair().unwrap();
+        (
+            Session::new(a, Party::garbler()),
+            Session::new(b, Party::evaluator()),
+        )
+    }
+
+    #[test]
+    fn test_messages() {
+        let (mut a, mut b) = pair();
+        a.send_message(b"hello").unwrap();
+        a.send_message(b"").unwrap();
+        assert_eq!(b.recv_message().unwrap(), b"hello");
+        assert_eq!(b.recv_message().unwrap(), b"");
+    }
+
+    #[test]
+    fn test_phase_mismatch() {
+        let (mut a, mut b) = pair();
+        a.phase = 1;
+        a.send_message(b"hello").unwrap();
+        match b.recv_message() {
+            Err(FrameError::PhaseMismatch {
+                local: 0,
+                remote: 1,
+            }) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_unexpected_frame() {
+        let (mut a, mut b) = pair();
+        a.write_frame(FRAME_PROTOCOL, &[]).unwrap();
+        match b.recv_message() {
+            Err(FrameError::UnexpectedFrame {
+                expected: "message",
+                found: "protocol",
+            }) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_too_large() {
+        let (mut a, b) = pair();
+        let mut b = b.max_message_len(4);
+        a.send_message(b"hello").unwrap();
+        match b.recv_message() {
+            Err(FrameError::TooLarge(5)) => (),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Marker types for the configuration state of a [`ProtocolDesc`](../struct.ProtocolDesc.html).
+//!
+//! A descriptor returned by [`protocol_desc`](../fn.protocol_desc.html) has neither a party nor a
+//! transport. Calling [`party`](../struct.ProtocolDesc.html#method.party) and one of the methods
+//! establishing a connection moves it to `ProtocolDesc<PartySet, Connected>`, which is the only
+//! state that can execute protocols.
+
+/// No party id was set yet.
+#[derive(Debug)]
+pub enum PartyUnset {}
+
+/// The party id was set via [`party`](../struct.ProtocolDesc.html#method.party).
+#[derive(Debug)]
+pub enum PartySet {}
+
+/// No transport was set yet.
+#[derive(Debug)]
+pub enum Disconnected {}
+
+/// A transport was set, e.g., via [`accept`](../struct.ProtocolDesc.html#method.accept),
+/// [`connect`](../struct.ProtocolDesc.html#method.connect), or
+/// [`use_stream`](../struct.ProtocolDesc.html#method.use_stream).
+#[derive(Debug)]
+pub enum Connected {}
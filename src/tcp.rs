@@ -0,0 +1,236 @@
+use std::ffi::CStr;
+use std::io;
+use std::mem;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use libc;
+use libobliv_sys::ProtocolTransport;
+
+use super::{ConnectionError, ConnectionErrorKind};
+
+/// Selects which of Obliv-C's native TCP transports is created by
+/// [`ProtocolDesc::accept`](struct.ProtocolDesc.html#method.accept) and
+/// [`ProtocolDesc::connect`](struct.ProtocolDesc.html#method.connect).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpMode {
+    /// The default transport
+    Plain,
+    /// A transport that counts the bytes sent and the number of flushes.
+    /// See [`ProtocolDesc::tcp_stats`](struct.ProtocolDesc.html#method.tcp_stats).
+    Profiled,
+    /// A transport on a socket with TCP keep-alive probes enabled, which stays open until the
+    /// [`ProtocolDesc`](struct.ProtocolDesc.html) is dropped. Probes are sent after 60 seconds
+    /// without traffic and every 10 seconds afterwards; the connection fails once six of them
+    /// are unanswered.
+    KeepAlive,
+}
+
+/// Profiling counters of a native TCP connection created in
+/// [`TcpMode::Profiled`](enum.TcpMode.html).
+///
+/// The counters are updated when the protocol execution finishes, so they can be read after the
+/// [`ProtocolDesc`](struct.ProtocolDesc.html) has been consumed. Handles can be cloned and sent to
+/// other threads.
+#[derive(Clone, Debug, Default)]
+pub struct TcpStats {
+    counters: Arc<(AtomicUsize, AtomicUsize)>,
+}
+impl TcpStats {
+    /// Returns the number of bytes sent over the connection.
+    pub fn bytes_sent(&self) -> usize {
+        (self.counters.0).load(Ordering::SeqCst)
+    }
+
+    /// Returns how often the connection was flushed.
+    pub fn flush_count(&self) -> usize {
+        (self.counters.1).load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn update(&self, bytes_sent: usize, flush_count: usize) {
+        (self.counters.0).store(bytes_sent, Ordering::SeqCst);
+        (self.counters.1).store(flush_count, Ordering::SeqCst);
+    }
+}
+
+// Formats `host` and `port` as an address accepted by `TcpStream::connect`.
+pub(crate) fn address(host: &CStr, port: &CStr) -> Result<String, ConnectionError> {
+    match (host.to_str(), port.to_str()) {
+        (Ok(host), Ok(port)) => Ok(format!("{}:{}", host, port)),
+        _ => Err(ConnectionError::new(
+            ConnectionErrorKind::InvalidInput,
+            "Host and port must be valid UTF-8",
+        )),
+    }
+}
+
+// Parses `port` as a port number.
+pub(crate) fn port(port: &CStr) -> Result<u16, ConnectionError> {
+    port.to_str()
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok())
+        .ok_or_else(|| ConnectionError::new(ConnectionErrorKind::InvalidInput, "Invalid port"))
+}
+
+// Accepts a single connection on `port` of all interfaces, like Obliv-C's native accept.
+pub(crate) fn accept(port: &CStr) -> Result<TcpStream, ConnectionError> {
+    let listener = TcpListener::bind(("0.0.0.0", self::port(port)?))?;
+    let (stream, _) = listener.accept()?;
+    Ok(stream)
+}
+
+// Makes a single attempt to connect to `addr`, distinguishing failures to resolve the address
+// from failures to connect.
+pub(crate) fn connect<A: ToSocketAddrs>(addr: A) -> Result<TcpStream, ConnectionError> {
+    let addrs: Vec<SocketAddr> = addr
+        .to_socket_addrs()
+        .map_err(|e| ConnectionError::new(ConnectionErrorKind::Resolve, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(ConnectionError::new(
+            ConnectionErrorKind::Resolve,
+            "Address resolved to an empty list",
+        ));
+    }
+    TcpStream::connect(&addrs[..]).map_err(|e| ConnectionError::from(e).with_peer(addrs[0]))
+}
+
+// Time a connection has to be idle before the first keep-alive probe is sent
+const KEEPALIVE_IDLE_SECS: c_int = 60;
+// Time between unanswered keep-alive probes
+const KEEPALIVE_INTERVAL_SECS: c_int = 10;
+// Number of unanswered probes after which the connection is considered dead
+const KEEPALIVE_PROBES: c_int = 6;
+
+// The socket option setting the idle time, which has a different name on Apple platforms
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+const TCP_KEEPIDLE: c_int = libc::TCP_KEEPALIVE;
+#[cfg(not(any(target_os = "macos", target_os = "ios")))]
+const TCP_KEEPIDLE: c_int = libc::TCP_KEEPIDLE;
+
+// Enables TCP keep-alive probes on `stream`. Unlike the system defaults, which usually wait two
+// hours before probing, a dead peer is detected after about two minutes.
+pub(crate) fn set_keepalive(stream: &TcpStream) -> io::Result<()> {
+    let fd = stream.as_raw_fd();
+    setsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+    setsockopt(fd, libc::IPPROTO_TCP, TCP_KEEPIDLE, KEEPALIVE_IDLE_SECS)?;
+    setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, KEEPALIVE_INTERVAL_SECS)?;
+    setsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT, KEEPALIVE_PROBES)
+}
+
+fn setsockopt(fd: RawFd, level: c_int, name: c_int, value: c_int) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const _ as *const libc::c_void,
+            mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Returns the socket of a transport created by one of Obliv-C's `protocolUseTcp2P` variants.
+// Obliv-C's private `tcp2PTransport` starts with the transport callbacks, followed by the
+// socket. As a change of that layout must not make a `CancelHandle` shut down an unrelated file
+// descriptor, the socket is only returned if it is a TCP socket whose peer port (for clients) or
+// local port (for servers) is `port`, if known.
+pub(crate) unsafe fn native_socket(
+    trans: *mut ProtocolTransport,
+    port: Option<u16>,
+    is_client: bool,
+) -> Option<RawFd> {
+    #[repr(C)]
+    struct Tcp2PTransport {
+        cb: ProtocolTransport,
+        sock: c_int,
+    }
+    let fd = (*(trans as *mut Tcp2PTransport)).sock;
+    if getsockopt(fd, libc::SOL_SOCKET, libc::SO_TYPE).ok()? != libc::SOCK_STREAM {
+        return None;
+    }
+    // borrow the socket to query its addresses, without closing it afterwards
+    let stream = mem::ManuallyDrop::new(TcpStream::from_raw_fd(fd));
+    let addr = if is_client {
+        stream.peer_addr()
+    } else {
+        stream.local_addr()
+    };
+    match (addr, port) {
+        (Ok(addr), Some(port)) if addr.port() != port => None,
+        (Ok(_), _) => Some(fd),
+        (Err(_), _) => None,
+    }
+}
+
+fn getsockopt(fd: RawFd, level: c_int, name: c_int) -> io::Result<c_int> {
+    let mut value: c_int = 0;
+    let mut len = mem::size_of_val(&value) as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(fd, level, name, &mut value as *mut _ as *mut libc::c_void, &mut len)
+    };
+    if ret == 0 {
+        Ok(value)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_keepalive() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        set_keepalive(&stream).unwrap();
+        let fd = stream.as_raw_fd();
+        assert_ne!(getsockopt(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE).unwrap(), 0);
+        assert_eq!(getsockopt(fd, libc::IPPROTO_TCP, TCP_KEEPIDLE).unwrap(), KEEPALIVE_IDLE_SECS);
+        assert_eq!(
+            getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL).unwrap(),
+            KEEPALIVE_INTERVAL_SECS
+        );
+        assert_eq!(
+            getsockopt(fd, libc::IPPROTO_TCP, libc::TCP_KEEPCNT).unwrap(),
+            KEEPALIVE_PROBES
+        );
+    }
+
+    #[test]
+    fn test_native_socket() {
+        #[repr(C)]
+        struct Transport {
+            cb: ProtocolTransport,
+            sock: c_int,
+        }
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        let native_socket = |fd, port, is_client| unsafe {
+            let mut trans = Transport {
+                cb: mem::zeroed(),
+                sock: fd,
+            };
+            native_socket(&mut trans as *mut Transport as *mut ProtocolTransport, port, is_client)
+        };
+        let (client, server) = (client.as_raw_fd(), server.as_raw_fd());
+        assert_eq!(native_socket(client, Some(port), true), Some(client));
+        assert_eq!(native_socket(server, Some(port), false), Some(server));
+        assert_eq!(native_socket(client, None, true), Some(client));
+        // a socket connected to another port, or not a socket at all
+        assert_eq!(native_socket(client, Some(port), false), None);
+        assert_eq!(native_socket(libc::STDIN_FILENO, None, true), None);
+        assert_eq!(native_socket(-1, None, true), None);
+    }
+}
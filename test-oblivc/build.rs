@@ -1,27 +1,37 @@
 extern crate oblivc;
 
 use std::env;
-use std::path::PathBuf;
 
 fn main() {
-    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
-
-    // Compile `millionaire.oc` using oblivcc
-    oblivc::compiler()
+    // Compile `millionaire.oc` using oblivcc, generate Rust bindings for the Obliv-C function and
+    // struct in `millionaire.h`, and write them to `OUT_DIR/millionaire.rs`. The build script is
+    // rerun whenever one of these files or the headers they include change.
+    oblivc::Build::new()
         .file("src/millionaire.oc")
+        .header("src/millionaire.h")
+        .function("millionaire")
+        .type_("millionaire_args")
         .include("src")
         .compile("millionaire");
 
-    // Generate Rust bindings for the Obliv-C function and struct in `millionaire.h`, then
-    // write them to `OUT_DIR/millionaire.rs`.
-    oblivc::bindings()
-        .header("src/millionaire.h")
-        .generate()
-        .unwrap()
-        .write_to_file(out_dir.join("millionaire.rs"))
-        .unwrap();
+    // With the `shared` feature, `tests/shared.rs` also loads `libequal.so`, which is not linked
+    // into the tests.
+    if env::var_os("CARGO_FEATURE_SHARED").is_some() {
+        oblivc::Build::new()
+            .file("src/equal.oc")
+            .include("src")
+            .cargo_metadata(false)
+            .compile("equal");
+    }
 
-    // Rebuild if either of the files change
-    println!("cargo:rerun-if-changed=src/millionaire.h");
-    println!("cargo:rerun-if-changed=src/millionaire.oc");
+    // With the `extensions` feature, `tests/extensions.rs` uses Obliv-C's ORAM library.
+    if env::var_os("CARGO_FEATURE_EXTENSIONS").is_some() {
+        oblivc::Build::new()
+            .file("src/oram_roundtrip.oc")
+            .header("src/oram_roundtrip.h")
+            .function("oram_roundtrip")
+            .type_("oram_roundtrip_args")
+            .include("src")
+            .compile("oram_roundtrip");
+    }
 }
@@ -0,0 +1,26 @@
+//! Runs the millionaire's problem as party 2 over standard input and output.
+//!
+//! Usage: `stdio_peer <input>`. Prints the output to standard error and exits successfully if
+//! party 1's input was less than `input`.
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use std::env;
+use std::process;
+use test_oblivc::{millionaire, millionaire_args};
+use oblivc::Party;
+
+fn main() {
+    let input = env::args()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .expect("Usage: stdio_peer <input>");
+    let mut args = millionaire_args { input, output: 0 };
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stdio();
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+    eprintln!("output: {}", args.output);
+    process::exit(if args.output < 0 { 0 } else { 1 });
+}
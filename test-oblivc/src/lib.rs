@@ -5,30 +5,24 @@
 //! equal to, or greater than party 2's.
 //!
 //! ## Compilation
-//! First, in [`build.rs`][4], we compile [`millionaire.oc`][5] using the Obliv-C compiler.
-//! This also tells Cargo to link the resulting objects after compiling our rust source code.
+//! In [`build.rs`][4], we compile [`millionaire.oc`][5] using the Obliv-C compiler, and generate
+//! Rust bindings for both the `millionaire` function and the `millionaire_args` struct declared
+//! in [`millionaire.h`][6]. This also tells Cargo to link the resulting objects after compiling
+//! our Rust source code, and to rerun the build script if any of the Obliv-C files change.
 //!
 //! ```no_run
 //! # extern crate oblivc;
-//! oblivc::compiler()
+//! oblivc::Build::new()
 //!     .file("src/millionaire.oc")
+//!     .header("src/millionaire.h")
+//!     .function("millionaire")
+//!     .type_("millionaire_args")
 //!     .include("src")
 //!     .compile("millionaire");
 //! ```
-//!
-//! Next, we generate Rust bindings for both the `millionaire` function and the `millionaire_args`
-//! struct defined in [`millionaire.h`][6].
-//!
-//! ```
-//! # let out_dir = std::path::PathBuf::from(env!("OUT_DIR"));
-//! # extern crate oblivc;
-//! oblivc::bindings()
-//!     .header("src/millionaire.h")
-//!     .generate().unwrap()
-//!     .write_to_file(out_dir.join("millionaire.rs")).unwrap();
-//! ```
-//! Note that for small projects, these bindings can also be generated by hand. Using `bindgen`
-//! is just more convenient.
+//! The bindings are written to `millionaire.rs` in Cargo's output directory. Note that for small
+//! projects, these bindings can also be written by hand. Using `bindgen` is just more
+//! convenient.
 //!
 //! ## Calling Obliv-C from Rust
 //! In our Rust source files, we first include the bindings generated by the build script.
@@ -53,18 +47,18 @@
 //!     output: 0,
 //! };
 //! let pd = oblivc::protocol_desc()
-//!     .party(1)
+//!     .party(oblivc::Party::garbler())
 //!     .accept("56734").unwrap();
-//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # });
 //! # let mut args = millionaire_args {
 //! #     input: 20,
 //! #     output: 0,
 //! # };
 //! # let pd = oblivc::protocol_desc()
-//! #     .party(2)
+//! #     .party(oblivc::Party::evaluator())
 //! #     .connect("localhost", "56734").unwrap();
-//! # unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! # unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # server.join().unwrap();
 //! # assert!(args.output == -1);
 //! # }
@@ -82,18 +76,18 @@
 //! #     output: 0,
 //! # };
 //! # let pd = oblivc::protocol_desc()
-//! #     .party(1)
+//! #     .party(oblivc::Party::garbler())
 //! #     .accept("67845").unwrap();
-//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # });
 //! let mut args = millionaire_args {
 //!     input: 20,
 //!     output: 0,
 //! };
 //! let pd = oblivc::protocol_desc()
-//!     .party(2)
+//!     .party(oblivc::Party::evaluator())
 //!     .connect("localhost", "67845").unwrap();
-//! # unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! # unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # server.join().unwrap();
 //! # assert!(args.output == -1);
 //! # }
@@ -111,18 +105,18 @@
 //! #     output: 0,
 //! # };
 //! # let pd = oblivc::protocol_desc()
-//! #     .party(1)
+//! #     .party(oblivc::Party::garbler())
 //! #     .accept("78956").unwrap();
-//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! #    unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # });
 //! # let mut args = millionaire_args {
 //! #     input: 20,
 //! #     output: 0,
 //! # };
 //! # let pd = oblivc::protocol_desc()
-//! #     .party(2)
+//! #     .party(oblivc::Party::evaluator())
 //! #     .connect("localhost", "78956").unwrap();
-//! unsafe { pd.exec_yao_protocol(millionaire, &mut args); }
+//! unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
 //! # server.join().unwrap();
 //! # assert!(args.output == -1);
 //! # }
@@ -132,6 +126,56 @@
 //! Obliv-C source code we wrote. It is up to the user of the `oblivc` crate to ensure their calls
 //! to [`exec_yao_protocol`][9] are safe.
 //!
+//! Forgetting to set up the [`ProtocolDesc`][8] is caught at compile time: only descriptors with
+//! both a party and a connection provide [`exec_yao_protocol`][9]. Neither of the following
+//! compiles.
+//!
+//! ```compile_fail
+//! # extern crate oblivc;
+//! # include!(concat!(env!("OUT_DIR"), "/millionaire.rs"));
+//! # fn main() {
+//! # let mut args = millionaire_args {
+//! #     input: 0,
+//! #     output: 0,
+//! # };
+//! // no party
+//! let pd = oblivc::protocol_desc();
+//! unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
+//! # }
+//! ```
+//!
+//! ```compile_fail
+//! # extern crate oblivc;
+//! # include!(concat!(env!("OUT_DIR"), "/millionaire.rs"));
+//! # fn main() {
+//! # let mut args = millionaire_args {
+//! #     input: 0,
+//! #     output: 0,
+//! # };
+//! // no connection
+//! let pd = oblivc::protocol_desc().party(oblivc::Party::garbler());
+//! unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
+//! # }
+//! ```
+//!
+//! Once both are set, the same call compiles:
+//!
+//! ```no_run
+//! # extern crate oblivc;
+//! # include!(concat!(env!("OUT_DIR"), "/millionaire.rs"));
+//! # fn main() {
+//! # let mut args = millionaire_args {
+//! #     input: 0,
+//! #     output: 0,
+//! # };
+//! let pd = oblivc::protocol_desc()
+//!     .party(oblivc::Party::garbler())
+//!     .accept("56733")
+//!     .unwrap();
+//! unsafe { pd.exec_yao_protocol(millionaire, &mut args).unwrap(); }
+//! # }
+//! ```
+//!
 //! ## Other Transports
 //! Besides native sockets from Obliv-C's interface, any objects that implement [`Read`][10] and
 //! [`Write`][11] can be used as transport for the protocol execution.
@@ -158,34 +202,5 @@
 extern crate oblivc;
 
 include!(concat!(env!("OUT_DIR"), "/millionaire.rs"));
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    #[should_panic]
-    fn test_no_party() {
-        let mut args = millionaire_args {
-            input: 0,
-            output: 0,
-        };
-        let pd = oblivc::protocol_desc();
-        unsafe {
-            pd.exec_yao_protocol(millionaire, &mut args);
-        }
-    }
-
-    #[test]
-    #[should_panic]
-    fn test_no_trans() {
-        let mut args = millionaire_args {
-            input: 0,
-            output: 0,
-        };
-        let pd = oblivc::protocol_desc().party(1);
-        unsafe {
-            pd.exec_yao_protocol(millionaire, &mut args);
-        }
-    }
-}
+#[cfg(feature = "extensions")]
+include!(concat!(env!("OUT_DIR"), "/oram_roundtrip.rs"));
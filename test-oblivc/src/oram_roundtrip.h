@@ -0,0 +1,14 @@
+#define ORAM_ROUNDTRIP_SIZE 8
+
+/// Arguments to `oram_roundtrip`, which is only built with the `extensions` feature.
+typedef struct {
+  /// The values party 1 writes into the ORAM
+  int values[ORAM_ROUNDTRIP_SIZE];
+  /// The index party 2 reads from the ORAM
+  int index;
+  /// The value read, revealed to both parties
+  int output;
+} oram_roundtrip_args;
+
+/// Writes the values of party 1 into an ORAM and reads the element at the index of party 2.
+void oram_roundtrip(void *arg);
@@ -0,0 +1,24 @@
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::{ChildStream, Party};
+use std::process::Command;
+use test_oblivc::{millionaire, millionaire_args};
+
+#[test]
+/// Runs a two-party protocol with a child process that communicates via stdin/stdout
+fn test_child_stream() {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_stdio_peer"));
+    command.arg("20");
+    let mut child = ChildStream::spawn(command).unwrap();
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc().party(Party::garbler()).use_stream(&mut child);
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+    assert!(args.output == -1);
+    assert!(child.wait().unwrap().success());
+}
@@ -0,0 +1,32 @@
+#![cfg(feature = "extensions")]
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::Party;
+use test_oblivc::{oram_roundtrip, oram_roundtrip_args};
+use std::os::unix::net::UnixStream;
+use std::thread;
+
+fn run(mut stream: UnixStream, party: Party, values: [i32; 8], index: i32) -> i32 {
+    let mut args = oram_roundtrip_args {
+        values,
+        index,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc().party(party).use_stream(&mut stream);
+    unsafe {
+        pd.exec_yao_protocol(oram_roundtrip, &mut args).unwrap();
+    }
+    args.output
+}
+
+#[test]
+/// Writes values into an ORAM of the `extensions` feature and reads one back at a secret index.
+fn test_oram_roundtrip() {
+    let values = [3, 1, 4, 1, 5, 9, 2, 6];
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || run(stream1, Party::garbler(), values, 0));
+    assert_eq!(run(stream2, Party::evaluator(), [0; 8], 5), 9);
+    assert_eq!(server.join().unwrap(), 9);
+}
@@ -0,0 +1,49 @@
+#![cfg(unix)]
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::{HandshakeError, Party, ProtocolMode};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use test_oblivc::{millionaire, millionaire_args};
+
+fn run(mut stream: UnixStream, party: Party, protocol: &'static str) -> Result<i8, HandshakeError> {
+    let mut args = millionaire_args {
+        input: 10 * party.id(),
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc()
+        .party(party)
+        .use_stream(&mut stream)
+        .handshake::<millionaire_args>(protocol, ProtocolMode::Yao)?;
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+    Ok(args.output)
+}
+
+#[test]
+/// Runs a protocol after a successful handshake
+fn test_handshake() {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || run(stream1, Party::garbler(), "millionaire"));
+    assert_eq!(run(stream2, Party::evaluator(), "millionaire").unwrap(), -1);
+    assert_eq!(server.join().unwrap().unwrap(), -1);
+}
+
+#[test]
+/// Checks that both parties detect that they want to run different protocols
+fn test_handshake_mismatch() {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || run(stream1, Party::garbler(), "millionaire"));
+    let results = [run(stream2, Party::evaluator(), "billionaire"), server.join().unwrap()];
+    for result in &results {
+        match *result {
+            Err(HandshakeError::Mismatch {
+                field: "protocol", ..
+            }) => (),
+            ref r => panic!("unexpected result {:?}", r),
+        }
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(unix)]
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::{ExecError, Party};
+use test_oblivc::{millionaire, millionaire_args};
+use std::thread;
+use std::os::unix::net::UnixStream;
+use std::io::{self, Read, Write};
+use std::process;
+use std::time::Duration;
+
+fn run_peer(mut stream: UnixStream) {
+    let mut args = millionaire_args {
+        input: 20,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stream(&mut stream);
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+}
+
+#[test]
+/// Checks that the results of an isolated execution are copied back from the helper process.
+fn test_isolated() {
+    let (mut stream, peer) = UnixStream::pair().unwrap();
+    let peer = thread::spawn(move || run_peer(peer));
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc()
+        .party(Party::garbler())
+        .use_stream(&mut stream)
+        .isolated();
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+    // party 1 < party 2
+    assert!(args.output == -1);
+    peer.join().unwrap();
+}
+
+// A stream that aborts the process when written to, like Obliv-C does on fatal errors
+struct AbortingStream(UnixStream);
+impl Read for AbortingStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl Write for AbortingStream {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        // bypass the output capturing of the test harness
+        let _ = writeln!(io::stderr(), "fatal transport error");
+        process::abort();
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[test]
+/// Checks that an aborted helper process is reported instead of terminating the test.
+fn test_isolated_abort() {
+    let (stream, _peer) = UnixStream::pair().unwrap();
+    let mut stream = AbortingStream(stream);
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc()
+        .party(Party::garbler())
+        .use_stream(&mut stream)
+        .isolated();
+    match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+        Err(ExecError::ChildAborted { status, stderr }) => {
+            assert!(!status.success());
+            assert!(stderr.contains("fatal transport error"));
+        }
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the helper process to abort"),
+    }
+}
+
+#[test]
+/// Checks that a helper process waiting for a silent peer is killed after the timeout.
+fn test_isolated_timeout() {
+    let (mut stream, _peer) = UnixStream::pair().unwrap();
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc()
+        .party(Party::garbler())
+        .use_stream(&mut stream)
+        .isolated()
+        .isolation_timeout(Duration::from_millis(200));
+    match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+        Err(ExecError::Isolation(e)) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the helper process to time out"),
+    }
+}
@@ -2,39 +2,205 @@ extern crate oblivc;
 extern crate test_oblivc;
 
 use test_oblivc::{millionaire, millionaire_args};
+use oblivc::{ConnectionErrorKind, Direction, ExecError, Party, RetryPolicy, TcpMode, TcpStats};
+use std::error::Error;
 use std::thread;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
 
-fn run_server() {
+fn run_server(mode: TcpMode, port: &'static str) -> Option<TcpStats> {
     let mut args = millionaire_args {
         input: 10,
         output: 0,
     };
-    let pd = oblivc::protocol_desc().party(1).accept("56734").unwrap();
+    let pd = oblivc::protocol_desc()
+        .party(Party::garbler())
+        .tcp_mode(mode)
+        .accept(port)
+        .unwrap();
+    let stats = pd.tcp_stats();
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
+    stats
 }
 
-fn run_client() {
+fn run_client(mode: TcpMode, port: &'static str) -> Option<TcpStats> {
     let mut args = millionaire_args {
         input: 20,
         output: 0,
     };
     let pd = oblivc::protocol_desc()
-        .party(2)
-        .connect("localhost", "56734")
+        .party(Party::evaluator())
+        .tcp_mode(mode)
+        .connect("localhost", port)
         .unwrap();
+    let stats = pd.tcp_stats();
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
     // party 1 < party 2
     assert!(args.output == -1);
+    stats
 }
 
 #[test]
 /// Runs a two-party protocol using Obliv-C's native connections
 fn test_native() {
-    let server = thread::spawn(run_server);
-    run_client();
+    let server = thread::spawn(|| run_server(TcpMode::Plain, "56734"));
+    assert!(run_client(TcpMode::Plain, "56734").is_none());
+    assert!(server.join().unwrap().is_none());
+}
+
+#[test]
+/// Runs a two-party protocol using profiled native connections and checks the counters
+fn test_native_profiled() {
+    let server = thread::spawn(|| run_server(TcpMode::Profiled, "56739"));
+    let client_stats = run_client(TcpMode::Profiled, "56739").unwrap();
+    let server_stats = server.join().unwrap().unwrap();
+    for stats in &[client_stats, server_stats] {
+        assert!(stats.bytes_sent() > 0);
+        assert!(stats.flush_count() > 0);
+    }
+}
+
+#[test]
+/// Runs a two-party protocol using native connections on keep-alive sockets
+fn test_native_keep_alive() {
+    let server = thread::spawn(|| run_server(TcpMode::KeepAlive, "56740"));
+    run_client(TcpMode::KeepAlive, "56740");
+    server.join().unwrap();
+}
+
+#[test]
+/// Runs several two-party protocols on the same native connection
+fn test_native_reuse() {
+    let inputs = [5, 10, 15];
+    let server = thread::spawn(move || {
+        let mut pd = oblivc::protocol_desc().party(Party::garbler()).accept("56741").unwrap();
+        for _ in 0..inputs.len() {
+            let mut args = millionaire_args {
+                input: 10,
+                output: 0,
+            };
+            unsafe {
+                pd.run_yao_protocol(millionaire, &mut args).unwrap();
+            }
+        }
+    });
+    let mut pd = oblivc::protocol_desc()
+        .party(Party::evaluator())
+        .connect("localhost", "56741")
+        .unwrap();
+    let outputs: Vec<_> = inputs
+        .iter()
+        .map(|&input| {
+            let mut args = millionaire_args { input, output: 0 };
+            unsafe {
+                pd.run_yao_protocol(millionaire, &mut args).unwrap();
+            }
+            args.output
+        })
+        .collect();
+    assert_eq!(outputs, vec![1, 0, -1]);
+    server.join().unwrap();
+}
+
+#[test]
+/// Cancels a protocol waiting for a peer that never sends anything
+fn test_native_cancel() {
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let peer = thread::spawn(move || {
+        let _stream = oblivc::connect_stream("localhost:56743", &RetryPolicy::default()).unwrap();
+        // keep the connection open until the test is done
+        let _ = done_rx.recv();
+    });
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).accept("56743").unwrap();
+    let cancel = pd.cancel_handle();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        cancel.cancel();
+    });
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+        Err(ExecError::Cancelled) => (),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the execution to be cancelled"),
+    }
+    done_tx.send(()).unwrap();
+    peer.join().unwrap();
+}
+
+#[test]
+/// Reports the failure of a native connection closed by the peer
+fn test_native_error() {
+    let peer = thread::spawn(|| {
+        // close the connection right away
+        oblivc::connect_stream("localhost:56749", &RetryPolicy::default()).unwrap();
+    });
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).accept("56749").unwrap();
+    peer.join().unwrap();
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+        Err(ExecError::Io { direction, .. }) => assert_eq!(direction, Direction::Unknown),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the execution to fail"),
+    }
+}
+
+#[test]
+/// Reports the progress of a protocol using a native connection
+fn test_native_progress() {
+    let server = thread::spawn(|| run_server(TcpMode::Plain, "56745"));
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let pd = {
+        let reports = reports.clone();
+        oblivc::protocol_desc()
+            .party(Party::evaluator())
+            .on_progress(Duration::from_millis(1), move |progress| {
+                reports.lock().unwrap().push(progress.clone())
+            })
+            .connect("localhost", "56745")
+            .unwrap()
+    };
+    let mut args = millionaire_args {
+        input: 20,
+        output: 0,
+    };
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
     server.join().unwrap();
+    let reports = reports.lock().unwrap();
+    let last = reports.last().unwrap();
+    assert!(last.bytes_sent() > 0);
+    assert!(last.bytes_received() > 0);
+    for pair in reports.windows(2) {
+        assert!(pair[0].bytes_sent() <= pair[1].bytes_sent());
+        assert!(pair[0].elapsed() <= pair[1].elapsed());
+    }
+}
+
+#[test]
+/// Checks the error returned when nobody is listening
+fn test_native_refused() {
+    let err = match oblivc::protocol_desc()
+        .party(Party::evaluator())
+        .connect_once("localhost", "56746")
+    {
+        Err(e) => e,
+        Ok(_) => panic!("Expected the connection to be refused"),
+    };
+    assert_eq!(err.kind(), ConnectionErrorKind::Refused);
+    assert!(err.is_transient());
+    assert_eq!(err.attempts(), 1);
+    assert_eq!(err.peer(), Some("localhost:56746"));
+    assert!(err.source().is_some());
 }
@@ -0,0 +1,107 @@
+extern crate oblivc;
+extern crate test_oblivc;
+
+use test_oblivc::{millionaire, millionaire_args};
+use oblivc::server::{Server, SessionError};
+use oblivc::{ExecError, Party, RetryPolicy};
+use std::thread;
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+const NUM_CLIENTS: usize = 4;
+
+fn run_client(addr: std::net::SocketAddr, input: i32) -> i8 {
+    let mut args = millionaire_args { input, output: 0 };
+    let mut stream = oblivc::connect_stream(addr, &RetryPolicy::default()).unwrap();
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stream(&mut stream);
+    unsafe {
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+    }
+    args.output
+}
+
+#[test]
+/// Runs several two-party protocols against a single server
+fn test_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = unsafe { Server::new(listener, millionaire) }.workers(2);
+    let shutdown = server.shutdown_handle();
+
+    // clients with inputs 0, 10, 20, ... compare against the server's 15
+    let clients: Vec<_> = (0..NUM_CLIENTS)
+        .map(|i| thread::spawn(move || run_client(addr, 10 * i as i32)))
+        .collect();
+
+    let mut outcomes = Vec::new();
+    server
+        .run(
+            |_| millionaire_args {
+                input: 15,
+                output: 0,
+            },
+            |outcome| {
+                outcomes.push(outcome.result.unwrap().output);
+                if outcomes.len() == NUM_CLIENTS {
+                    shutdown.shutdown();
+                }
+            },
+        )
+        .unwrap();
+
+    let mut client_outputs: Vec<_> = clients.into_iter().map(|c| c.join().unwrap()).collect();
+    // the server sees the negated result of each client
+    client_outputs.sort();
+    outcomes.sort();
+    outcomes.reverse();
+    assert_eq!(client_outputs, vec![-1, -1, 1, 1]);
+    assert_eq!(
+        outcomes,
+        client_outputs.iter().map(|o| -o).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+/// Checks that a client that never responds does not block the only worker forever.
+fn test_server_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = unsafe { Server::new(listener, millionaire) }
+        .workers(1)
+        .session_timeout(Some(Duration::from_millis(200)));
+    let shutdown = server.shutdown_handle();
+
+    // the silent client is accepted first and occupies the worker until it times out
+    let silent = TcpStream::connect(addr).unwrap();
+    let client = thread::spawn(move || run_client(addr, 20));
+
+    let mut results = Vec::new();
+    server
+        .run(
+            |_| millionaire_args {
+                input: 15,
+                output: 0,
+            },
+            |outcome| {
+                results.push((outcome.id, outcome.result));
+                if results.len() == 2 {
+                    shutdown.shutdown();
+                }
+            },
+        )
+        .unwrap();
+    drop(silent);
+
+    // party 1 < party 2
+    assert_eq!(client.join().unwrap(), -1);
+    results.sort_by_key(|&(id, _)| id);
+    match results[0].1 {
+        Err(SessionError::Exec(ExecError::Io { .. })) => (),
+        Err(ref e) => panic!("Unexpected error: {}", e),
+        Ok(_) => panic!("Expected the silent session to fail"),
+    }
+    match results[1].1 {
+        Ok(ref args) => assert_eq!(args.output, -1),
+        Err(ref e) => panic!("Unexpected error: {}", e),
+    }
+}
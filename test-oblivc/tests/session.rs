@@ -0,0 +1,71 @@
+#![cfg(unix)]
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::{FrameError, Party, Session};
+use std::os::unix::net::UnixStream;
+use std::thread;
+use test_oblivc::{millionaire, millionaire_args};
+
+fn run(mut session: Session<UnixStream>, input: i32) -> Vec<i8> {
+    let mut outputs = Vec::new();
+    for round in 0..2 {
+        // agree on the job before each run
+        session
+            .send_message(format!("job {}", round).as_bytes())
+            .unwrap();
+        assert_eq!(
+            session.recv_message().unwrap(),
+            format!("job {}", round).as_bytes()
+        );
+        let mut args = millionaire_args {
+            input: input + round,
+            output: 0,
+        };
+        unsafe {
+            session
+                .exec_yao_protocol("millionaire", millionaire, &mut args)
+                .unwrap();
+        }
+        outputs.push(args.output);
+    }
+    assert_eq!(session.phase(), 2);
+    outputs
+}
+
+#[test]
+/// Exchanges messages between protocol runs on the same stream
+fn test_session() {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || run(Session::new(stream1, Party::garbler()), 10));
+    assert_eq!(
+        run(Session::new(stream2, Party::evaluator()), 10),
+        vec![0, 0]
+    );
+    assert_eq!(server.join().unwrap(), vec![0, 0]);
+}
+
+#[test]
+/// Checks that a party expecting a message detects that the other one runs a protocol
+fn test_session_out_of_phase() {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || {
+        let mut session = Session::new(stream1, Party::garbler());
+        session.recv_message()
+    });
+    let mut session = Session::new(stream2, Party::evaluator());
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    // the garbler closes the stream after the error, so the execution fails as well
+    assert!(unsafe { session.exec_yao_protocol("millionaire", millionaire, &mut args) }.is_err());
+    match server.join().unwrap() {
+        Err(FrameError::UnexpectedFrame {
+            expected: "message",
+            found: "protocol",
+        }) => (),
+        r => panic!("unexpected result {:?}", r),
+    }
+}
@@ -0,0 +1,124 @@
+#![cfg(feature = "shared")]
+
+extern crate oblivc;
+extern crate test_oblivc;
+
+use oblivc::Party;
+use oblivc::dynamic::{CompileError, Compiler, Library, LoadError, Protocol};
+use test_oblivc::millionaire_args;
+use std::env;
+use std::fs;
+use std::os::raw::c_char;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+
+// Loads `lib<name>.so` built by the build script
+fn load(name: &str) -> Library {
+    let path = PathBuf::from(env!("OUT_DIR")).join(format!("lib{}.so", name));
+    unsafe { Library::open(path) }.unwrap()
+}
+
+fn run(
+    protocol: Protocol<millionaire_args>,
+    mut stream: UnixStream,
+    party: Party,
+    input: i32,
+) -> c_char {
+    let mut args = millionaire_args {
+        input,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc().party(party).use_stream(&mut stream);
+    unsafe {
+        protocol.exec_yao_protocol(pd, &mut args).unwrap();
+    }
+    args.output
+}
+
+// Executes `protocol` with the given inputs of the garbler and the evaluator
+fn execute(protocol: Protocol<millionaire_args>, garbler: i32, evaluator: i32) -> c_char {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let server_protocol = protocol.clone();
+    let server = thread::spawn(move || run(server_protocol, stream1, Party::garbler(), garbler));
+    let output = run(protocol, stream2, Party::evaluator(), evaluator);
+    assert_eq!(server.join().unwrap(), output);
+    output
+}
+
+#[test]
+/// Loads protocol libraries built by `oblivc::Build` with the `shared` feature at runtime and
+/// executes protocols found by their symbol name. `libequal.so` is not linked into the test, so
+/// its protocol is only available from the loaded library.
+fn test_shared_library() {
+    let millionaire = load("millionaire")
+        .protocol::<millionaire_args>("millionaire")
+        .unwrap();
+    assert_eq!(execute(millionaire, 10, 20), -1);
+    let equal = load("equal").protocol::<millionaire_args>("equal").unwrap();
+    assert_eq!(execute(equal.clone(), 10, 20), 0);
+    assert_eq!(execute(equal, 20, 20), 1);
+}
+
+#[test]
+/// Checks that a protocol cannot be loaded with an argument type of a different layout.
+fn test_shared_library_layout_mismatch() {
+    match load("millionaire").protocol::<[u8; 32]>("millionaire") {
+        Err(LoadError::LayoutMismatch { type_name, .. }) => {
+            assert_eq!(type_name, "millionaire_args")
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+// A cache directory that is only used by a single test and removed afterwards
+struct CacheDir(PathBuf);
+impl CacheDir {
+    fn new(test: &str) -> Self {
+        let dir = env::temp_dir().join(format!("oblivc-{}-{}", test, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        CacheDir(dir)
+    }
+
+    fn compiler(&self) -> Compiler {
+        Compiler::new(&self.0).include(concat!(env!("CARGO_MANIFEST_DIR"), "/src"))
+    }
+}
+impl Drop for CacheDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+/// Compiles the protocol from its source at runtime, loads it, and executes it.
+fn test_compile_at_runtime() {
+    let source = include_str!("../src/millionaire.oc");
+    let cache = CacheDir::new("compile-at-runtime");
+    let compiler = cache.compiler();
+    let path = compiler.compile(source).unwrap();
+    let inode = fs::metadata(&path).unwrap().ino();
+    // compiling again returns the cached library instead of replacing it
+    assert_eq!(compiler.compile(source).unwrap(), path);
+    assert_eq!(fs::metadata(&path).unwrap().ino(), inode);
+    assert_eq!(fs::read_dir(&cache.0).unwrap().count(), 1);
+
+    let library = unsafe { Library::open(&path) }.unwrap();
+    let protocol = library.protocol::<millionaire_args>("millionaire").unwrap();
+    assert_eq!(execute(protocol, 30, 20), 1);
+}
+
+#[test]
+/// Checks that compiler errors refer to the location in the source.
+fn test_compile_error() {
+    let source = "#include <obliv.oh>\n\nvoid broken(void* args) {\n  obliv int x = y;\n}\n";
+    let cache = CacheDir::new("compile-error");
+    match cache.compiler().compile(source) {
+        Err(CompileError::Compile { errors, .. }) => {
+            assert!(errors.iter().any(|e| e.starts_with("source.oc:4")), "{:?}", errors)
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
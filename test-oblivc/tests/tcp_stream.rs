@@ -2,8 +2,9 @@ extern crate oblivc;
 extern crate test_oblivc;
 
 use test_oblivc::{millionaire, millionaire_args};
+use oblivc::{Party, RetryPolicy};
 use std::thread;
-use std::net::{TcpListener, TcpStream};
+use std::net::TcpListener;
 use std::io::{Read, Write};
 
 fn run_server() {
@@ -15,18 +16,18 @@ fn run_server() {
     let listener = TcpListener::bind("0.0.0.0:56735").unwrap();
     let (mut stream, _) = listener.accept().unwrap();
     // Use this connection for our ProtocolDesc
-    let pd = oblivc::protocol_desc().use_stream(&mut stream).party(1);
+    let pd = oblivc::protocol_desc().use_stream(&mut stream).party(Party::garbler());
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
 
     // Read non-oblivc data sent by the client
     stream.read_exact(&mut [0; 4]).unwrap();
 
     // run again with roles reversed
-    let pd = oblivc::protocol_desc().use_stream(&mut stream).party(2);
+    let pd = oblivc::protocol_desc().use_stream(&mut stream).party(Party::evaluator());
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
 }
 
@@ -36,19 +37,11 @@ fn run_client() {
         output: 0,
     };
     // try connecting until successful
-    let mut stream = (0..)
-        .filter_map(|_| {
-            TcpStream::connect("localhost:56735").ok().or_else(|| {
-                thread::sleep(std::time::Duration::from_millis(100));
-                None
-            })
-        })
-        .next()
-        .unwrap();
+    let mut stream = oblivc::connect_stream("localhost:56735", &RetryPolicy::default()).unwrap();
     // use the connection once established
-    let pd = oblivc::protocol_desc().party(2).use_stream(&mut stream);
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stream(&mut stream);
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
     assert!(args.output == -1);
 
@@ -56,9 +49,9 @@ fn run_client() {
     stream.write_all(b"blah").unwrap();
 
     // use it for obliv-c again
-    let pd = oblivc::protocol_desc().party(1).use_stream(&mut stream);
+    let pd = oblivc::protocol_desc().party(Party::garbler()).use_stream(&mut stream);
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
     assert!(args.output == 1);
 }
@@ -3,18 +3,21 @@
 extern crate oblivc;
 extern crate test_oblivc;
 
+use oblivc::{ExecError, Party};
 use test_oblivc::{millionaire, millionaire_args};
 use std::thread;
 use std::os::unix::net::UnixStream;
+use std::io::{self, Read, Write};
+use std::panic;
 
 fn run_server(mut stream: UnixStream) {
     let mut args = millionaire_args {
         input: 10,
         output: 0,
     };
-    let pd = oblivc::protocol_desc().party(1).use_stream(&mut stream);
+    let pd = oblivc::protocol_desc().party(Party::garbler()).use_stream(&mut stream);
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
 }
 
@@ -23,9 +26,9 @@ fn run_client(mut stream: UnixStream) {
         input: 20,
         output: 0,
     };
-    let pd = oblivc::protocol_desc().party(2).use_stream(&mut stream);
+    let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stream(&mut stream);
     unsafe {
-        pd.exec_yao_protocol(millionaire, &mut args);
+        pd.exec_yao_protocol(millionaire, &mut args).unwrap();
     }
     // party 1 < party 2
     assert!(args.output == -1);
@@ -43,3 +46,72 @@ fn test_unix_stream() {
     run_client(stream2);
     server.join().unwrap();
 }
+
+// A stream whose writes panic
+struct PanickingStream(UnixStream);
+impl Read for PanickingStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl Write for PanickingStream {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        panic!("invariant violated");
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+#[test]
+/// Checks that a panic in a stream is resumed after the protocol execution instead of unwinding
+/// through Obliv-C.
+fn test_unix_stream_panic() {
+    let (stream1, stream2) = UnixStream::pair().unwrap();
+    let client = thread::spawn(move || {
+        let mut stream = stream2;
+        let mut args = millionaire_args {
+            input: 20,
+            output: 0,
+        };
+        let pd = oblivc::protocol_desc().party(Party::evaluator()).use_stream(&mut stream);
+        unsafe { pd.exec_yao_protocol(millionaire, &mut args) }
+    });
+    let result = panic::catch_unwind(move || {
+        let mut stream = PanickingStream(stream1);
+        let mut args = millionaire_args {
+            input: 10,
+            output: 0,
+        };
+        let pd = oblivc::protocol_desc().party(Party::garbler()).use_stream(&mut stream);
+        unsafe {
+            pd.exec_yao_protocol(millionaire, &mut args).unwrap();
+        }
+    });
+    let payload = result.unwrap_err();
+    assert_eq!(*payload.downcast::<&str>().unwrap(), "invariant violated");
+    // the client fails once the server's stream is closed
+    match client.join().unwrap() {
+        Err(ExecError::Io { .. }) => (),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the execution to fail"),
+    }
+}
+
+#[test]
+/// Checks that an I/O error of a stream is returned by the protocol execution.
+fn test_unix_stream_error() {
+    let (mut stream, peer) = UnixStream::pair().unwrap();
+    // the peer disconnects before the protocol starts, so the first operation fails
+    drop(peer);
+    let mut args = millionaire_args {
+        input: 10,
+        output: 0,
+    };
+    let pd = oblivc::protocol_desc().party(Party::garbler()).use_stream(&mut stream);
+    match unsafe { pd.exec_yao_protocol(millionaire, &mut args) } {
+        Err(ExecError::Io { offset: 0, .. }) => (),
+        Err(e) => panic!("Unexpected error: {}", e),
+        Ok(()) => panic!("Expected the execution to fail"),
+    }
+}